@@ -0,0 +1,365 @@
+use anyhow::{anyhow, Result};
+use bgpkit_broker::BrokerItem;
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// A single MRT dump file to process, from the broker or a local archive mirror.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpItem {
+    pub project: String,
+    pub collector_id: String,
+    /// `rib` or `updates`
+    pub data_type: String,
+    pub ts_start: NaiveDateTime,
+    /// URL or local path of the file
+    pub url: String,
+    /// file size in bytes, 0 if unknown
+    pub rough_size: i64,
+}
+
+#[cfg(test)]
+impl DumpItem {
+    /// A RIB dump of unknown size, for tests.
+    pub(crate) fn test_rib(collector_id: &str, ts_start: NaiveDateTime, url: &str) -> Self {
+        DumpItem {
+            project: project_from_collector(collector_id),
+            collector_id: collector_id.to_string(),
+            data_type: "rib".to_string(),
+            ts_start,
+            url: url.to_string(),
+            rough_size: 0,
+        }
+    }
+}
+
+/// Guess the project of a collector from its name, following the RIS (`rrcXX`) and RouteViews
+/// (`route-viewsX`, `route-views.X`) naming. Other collectors yield `unknown`.
+pub fn project_from_collector(collector_id: &str) -> String {
+    let is_ris = collector_id.len() == 5
+        && collector_id.starts_with("rrc")
+        && collector_id[3..].chars().all(|c| c.is_ascii_digit());
+    if is_ris {
+        "riperis".to_string()
+    } else if collector_id.starts_with("route-views") {
+        "route-views".to_string()
+    } else {
+        "unknown".to_string()
+    }
+}
+
+/// User-supplied projects of collectors, for collectors not following the RIS and RouteViews
+/// naming, e.g. PCH or private collectors.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectMapping {
+    projects: HashMap<String, String>,
+}
+
+impl ProjectMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a mapping with one `<collector> <project>` pair per line. Empty lines and lines
+    /// starting with `#` are ignored.
+    pub fn load<R: BufRead>(reader: R) -> Result<Self> {
+        let mut mapping = Self::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [collector, project] => mapping.insert(collector, project),
+                _ => {
+                    return Err(anyhow!(
+                        "invalid project mapping on line {}: {}",
+                        idx + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(mapping)
+    }
+
+    pub fn insert(&mut self, collector_id: &str, project: &str) {
+        self.projects
+            .insert(collector_id.to_string(), project.to_string());
+    }
+
+    /// Project of a collector, from the mapping or guessed from its name.
+    pub fn project(&self, collector_id: &str) -> String {
+        match self.projects.get(collector_id) {
+            Some(project) => project.clone(),
+            None => project_from_collector(collector_id),
+        }
+    }
+
+    /// Override the project of an item whose collector is in the mapping.
+    pub fn apply(&self, item: &mut DumpItem) {
+        if let Some(project) = self.projects.get(&item.collector_id) {
+            item.project = project.clone();
+        }
+    }
+}
+
+/// Collectors and projects to process. Collector patterns may use `*` and `?` wildcards, e.g.
+/// `rrc*` or `route-views*`. Empty include lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct CollectorFilter {
+    collectors: Vec<String>,
+    excluded: Vec<String>,
+    projects: Vec<String>,
+}
+
+impl CollectorFilter {
+    pub fn new(collectors: Vec<String>, excluded: Vec<String>, projects: Vec<String>) -> Self {
+        CollectorFilter {
+            collectors,
+            excluded,
+            projects,
+        }
+    }
+
+    /// Filter matching a single collector.
+    pub fn single(collector_id: &str) -> Self {
+        Self::new(vec![collector_id.to_string()], vec![], vec![])
+    }
+
+    /// The collector if exactly one is selected by name, to narrow down broker queries.
+    pub fn single_collector(&self) -> Option<&str> {
+        match self.collectors.as_slice() {
+            [c] if !c.contains(['*', '?']) => Some(c.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, item: &DumpItem) -> bool {
+        self.matches_collector(item.collector_id.as_str())
+            && (self.projects.is_empty() || self.projects.contains(&item.project))
+    }
+
+    /// Match the collector name only, ignoring the project filter.
+    pub fn matches_collector(&self, collector: &str) -> bool {
+        (self.collectors.is_empty() || self.collectors.iter().any(|p| glob_match(p, collector)))
+            && !self.excluded.iter().any(|p| glob_match(p, collector))
+    }
+}
+
+/// Match a name against a pattern with `*` (any sequence) and `?` (any character) wildcards.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    n = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl From<BrokerItem> for DumpItem {
+    fn from(item: BrokerItem) -> Self {
+        DumpItem {
+            project: project_from_collector(item.collector_id.as_str()),
+            collector_id: item.collector_id,
+            data_type: item.data_type,
+            ts_start: item.ts_start,
+            url: item.url,
+            rough_size: item.rough_size,
+        }
+    }
+}
+
+/// Parse the data type and dump time from a RouteViews or RIS dump file name, e.g.
+/// `rib.20220205.1800.bz2`, `bview.20220205.1600.gz` or `updates.20220205.1815.gz`.
+fn parse_dump_file_name(file_name: &str) -> Option<(String, NaiveDateTime)> {
+    let mut parts = file_name.split('.');
+    let data_type = match parts.next()? {
+        "rib" | "bview" => "rib",
+        "updates" => "updates",
+        _ => return None,
+    };
+    let date = parts.next()?;
+    let time = parts.next()?;
+    let ts =
+        NaiveDateTime::parse_from_str(format!("{}{}", date, time).as_str(), "%Y%m%d%H%M").ok()?;
+    Some((data_type.to_string(), ts))
+}
+
+/// Recover project, collector, data type and dump time from the path of a file in a local
+/// mirror of the RouteViews or RIS archives.
+///
+/// Supported layouts:
+/// - RouteViews: `[<collector>/]bgpdata/YYYY.MM/RIBS/rib.YYYYMMDD.HHMM.bz2` and
+///   `.../UPDATES/updates.YYYYMMDD.HHMM.bz2`, with `route-views2` as collector when the
+///   collector directory is absent, as on `archive.routeviews.org`.
+/// - RIS: `rrcXX/YYYY.MM/bview.YYYYMMDD.HHMM.gz` and `rrcXX/YYYY.MM/updates.YYYYMMDD.HHMM.gz`
+pub fn parse_archive_path(path: &str) -> Option<DumpItem> {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    let (file_name, dirs) = components.split_last()?;
+    let (data_type, ts_start) = parse_dump_file_name(file_name)?;
+
+    let (project, collector_id) = if let Some(idx) = dirs.iter().rposition(|d| *d == "bgpdata") {
+        let collector = match idx > 0 && dirs[idx - 1].starts_with("route-views") {
+            true => dirs[idx - 1].to_string(),
+            false => "route-views2".to_string(),
+        };
+        ("route-views".to_string(), collector)
+    } else {
+        let collector = dirs.iter().rev().find(|d| {
+            d.len() == 5 && d.starts_with("rrc") && d[3..].chars().all(|c| c.is_ascii_digit())
+        })?;
+        ("riperis".to_string(), collector.to_string())
+    };
+
+    Some(DumpItem {
+        project,
+        collector_id,
+        data_type,
+        ts_start,
+        url: path.to_string(),
+        rough_size: 0,
+    })
+}
+
+/// Walk a local archive mirror and collect all dump files of the given data type (`rib` or
+/// `updates`), or of both if `None`, with dump times within `[ts_start, ts_end]`, sorted by time
+/// and collector.
+pub fn scan_local_archive(
+    archive_dir: &Path,
+    data_type: Option<&str>,
+    ts_start: &NaiveDateTime,
+    ts_end: &NaiveDateTime,
+) -> Result<Vec<DumpItem>> {
+    let mut items = vec![];
+    for entry in WalkDir::new(archive_dir).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = match entry.path().to_str() {
+            Some(p) => p,
+            None => continue,
+        };
+        let mut item = match parse_archive_path(path) {
+            Some(i) => i,
+            None => continue,
+        };
+        if data_type.is_some_and(|t| t != item.data_type)
+            || item.ts_start < *ts_start
+            || item.ts_start > *ts_end
+        {
+            continue;
+        }
+        item.rough_size = entry.metadata().map(|m| m.len() as i64).unwrap_or_default();
+        items.push(item);
+    }
+    items.sort_by(|a, b| {
+        (a.ts_start, a.collector_id.as_str()).cmp(&(b.ts_start, b.collector_id.as_str()))
+    });
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_archive_path() {
+        let item =
+            parse_archive_path("/mirror/route-views.sg/bgpdata/2022.02/RIBS/rib.20220205.1800.bz2")
+                .unwrap();
+        assert_eq!(item.project, "route-views");
+        assert_eq!(item.collector_id, "route-views.sg");
+        assert_eq!(item.data_type, "rib");
+        assert_eq!(item.ts_start.and_utc().timestamp(), 1644084000);
+
+        let item = parse_archive_path("/mirror/bgpdata/2022.02/UPDATES/updates.20220205.1815.bz2")
+            .unwrap();
+        assert_eq!(item.collector_id, "route-views2");
+        assert_eq!(item.data_type, "updates");
+
+        let item = parse_archive_path("/mirror/ris/rrc00/2022.02/bview.20220205.1600.gz").unwrap();
+        assert_eq!(item.project, "riperis");
+        assert_eq!(item.collector_id, "rrc00");
+        assert_eq!(item.data_type, "rib");
+
+        assert!(parse_archive_path("/mirror/ris/rrc00/2022.02/README.txt").is_none());
+        assert!(parse_archive_path("/mirror/other/2022.02/bview.20220205.1600.gz").is_none());
+    }
+
+    #[test]
+    fn test_collector_filter() {
+        let item =
+            |collector_id: &str| DumpItem::test_rib(collector_id, NaiveDateTime::default(), "");
+        let filter = CollectorFilter::new(
+            vec!["rrc*".to_string(), "route-views?".to_string()],
+            vec!["rrc2?".to_string()],
+            vec![],
+        );
+        assert!(filter.matches(&item("rrc00")));
+        assert!(!filter.matches(&item("rrc21")));
+        assert!(filter.matches(&item("route-views2")));
+        assert!(!filter.matches(&item("route-views.sg")));
+        assert!(filter.single_collector().is_none());
+        assert!(filter.matches_collector("rrc00"));
+        assert!(!filter.matches_collector("rrc21"));
+
+        let filter = CollectorFilter::new(vec![], vec![], vec!["route-views".to_string()]);
+        assert!(filter.matches(&item("route-views.sg")));
+        assert!(!filter.matches(&item("rrc00")));
+        assert_eq!(
+            CollectorFilter::single("rrc00").single_collector(),
+            Some("rrc00")
+        );
+        assert!(glob_match("*views*", "route-views.sg"));
+        assert!(!glob_match("rrc*1", "rrc10"));
+    }
+
+    #[test]
+    fn test_project_mapping() {
+        let mapping =
+            ProjectMapping::load("# private collectors\npch-sg   pch\n\nrrc00 custom\n".as_bytes())
+                .unwrap();
+        assert_eq!(mapping.project("pch-sg"), "pch");
+        assert_eq!(mapping.project("rrc00"), "custom");
+        assert_eq!(mapping.project("rrc21"), "riperis");
+        assert_eq!(mapping.project("route-views.sg"), "route-views");
+        assert_eq!(mapping.project("collector1"), "unknown");
+
+        let mut item = parse_archive_path("/mirror/rrc00/2022.02/bview.20220205.1600.gz").unwrap();
+        mapping.apply(&mut item);
+        assert_eq!(item.project, "custom");
+
+        assert!(ProjectMapping::load("pch-sg\n".as_bytes()).is_err());
+    }
+}
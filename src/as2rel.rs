@@ -37,6 +37,26 @@ pub const CANDIDATE_TIER1_V6: [u32; 2] = [
     6939, // Hurricane Electric (IPv6 only) - only provides transit if connecting to a true tier-1
 ];
 
+/// Tier-1 ASes used to infer provider-customer relationships, [TRUE_TIER1],
+/// [CANDIDATE_TIER1_V4] and [CANDIDATE_TIER1_V6] by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tier1Config {
+    pub true_tier1: Vec<u32>,
+    pub candidate_tier1_v4: Vec<u32>,
+    pub candidate_tier1_v6: Vec<u32>,
+}
+
+impl Default for Tier1Config {
+    fn default() -> Self {
+        Tier1Config {
+            true_tier1: TRUE_TIER1.to_vec(),
+            candidate_tier1_v4: CANDIDATE_TIER1_V4.to_vec(),
+            candidate_tier1_v6: CANDIDATE_TIER1_V6.to_vec(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct As2Rel {
     pub project: String,
@@ -229,27 +249,34 @@ fn combine_as2rel_maps(
 pub struct As2RelProcessor {
     as2rel_v4_map: HashMap<(u32, u32, u8), (usize, HashSet<IpAddr>)>,
     as2rel_v6_map: HashMap<(u32, u32, u8), (usize, HashSet<IpAddr>)>,
+    tier1: Tier1Config,
 }
 
 impl As2RelProcessor {
     pub fn new() -> Self {
+        Self::with_tier1(Tier1Config::default())
+    }
+
+    /// Create a processor inferring relationships with the given tier-1 ASes.
+    pub fn with_tier1(tier1: Tier1Config) -> Self {
         Self {
             as2rel_v4_map: HashMap::new(),
             as2rel_v6_map: HashMap::new(),
+            tier1,
         }
     }
 
     /// Process a single AS path and update relationship statistics.
     ///
-    /// For IPv4: Uses TRUE_TIER1 and CANDIDATE_TIER1_V4
-    /// For IPv6: Uses TRUE_TIER1 and CANDIDATE_TIER1_V6
+    /// For IPv4: Uses the true tier-1 and IPv4 candidate tier-1 ASes
+    /// For IPv6: Uses the true tier-1 and IPv6 candidate tier-1 ASes
     pub fn process_path(&mut self, peer_ip: IpAddr, prefix_type: IpNet, as_path: &[u32]) {
         match prefix_type {
             IpNet::V4(_) => {
                 update_as2rel_map(
                     peer_ip,
-                    &TRUE_TIER1,
-                    &CANDIDATE_TIER1_V4,
+                    &self.tier1.true_tier1,
+                    &self.tier1.candidate_tier1_v4,
                     &mut self.as2rel_v4_map,
                     as_path,
                 );
@@ -257,8 +284,8 @@ impl As2RelProcessor {
             IpNet::V6(_) => {
                 update_as2rel_map(
                     peer_ip,
-                    &TRUE_TIER1,
-                    &CANDIDATE_TIER1_V6,
+                    &self.tier1.true_tier1,
+                    &self.tier1.candidate_tier1_v6,
                     &mut self.as2rel_v6_map,
                     as_path,
                 );
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Temporary file a file is written to before it is renamed into place.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Write a file atomically, see [write_atomic_checked].
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(File) -> Result<File>,
+{
+    write_atomic_checked(path, write, |_| Ok(()))
+}
+
+/// Write a file through a temporary file next to it, which is synced, passed to `check` and only
+/// renamed into place if all of this succeeded, so that a crash or failure never leaves a partially
+/// written file at the path. Missing parent directories are created.
+pub fn write_atomic_checked<F, C>(path: &Path, write: F, check: C) -> Result<()>
+where
+    F: FnOnce(File) -> Result<File>,
+    C: FnOnce(&Path) -> Result<()>,
+{
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("couldn't create directory {}", parent.display()))?;
+    }
+    let tmp_path = tmp_path(path);
+    let file =
+        File::create(&tmp_path).with_context(|| format!("couldn't open {}", tmp_path.display()))?;
+
+    let result = write(file).and_then(|file| {
+        file.sync_all()?;
+        check(&tmp_path)
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.context(format!("failed to write {}", path.display())));
+    }
+
+    fs::rename(&tmp_path, path).with_context(|| {
+        format!(
+            "couldn't rename {} to {}",
+            tmp_path.display(),
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::io::Write;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("write-atomic-test-{}", std::process::id()));
+        let path = dir.join("nested/data.json");
+
+        write_atomic(&path, |mut file| {
+            file.write_all(b"{}")?;
+            Ok(file)
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        assert!(!tmp_path(&path).exists());
+
+        // failed writes and checks leave the existing file untouched
+        assert!(write_atomic(&path, |_| Err(anyhow!("write failed"))).is_err());
+        assert!(write_atomic_checked(
+            &path,
+            |mut file| {
+                file.write_all(b"{")?;
+                Ok(file)
+            },
+            |_| Err(anyhow!("invalid"))
+        )
+        .is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        assert!(!tmp_path(&path).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,17 +1,39 @@
-use bgpkit_broker::{BgpkitBroker, BrokerItem};
-use bzip2::write::BzEncoder;
-use bzip2::Compression;
-use chrono::{Datelike, Timelike};
-use clap::Parser;
+use anyhow::{Context, Result};
+use bgpkit_broker::BgpkitBroker;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use indicatif::{ProgressBar, ProgressStyle};
-use peer_stats::parse_rib_file;
+use peer_stats::archive::scan_local_archive;
+use peer_stats::atomic::write_atomic_checked;
+use peer_stats::codec::{decompressed_reader, CompressedWriter};
+use peer_stats::config::BootstrapConfig;
+use peer_stats::format::{read_data_file, verify_parquet};
+use peer_stats::manifest::{
+    file_sha256, ItemStatus, ManifestOutput, ManifestRecord, ManifestReport, RunManifest,
+};
+use peer_stats::retry::{FailureKind, RetryPolicy};
+use peer_stats::scheduler::{estimate_memory, parse_size, MemoryBudget};
+use peer_stats::selection::{selection_report, Selection};
+use peer_stats::summary::RunSummary;
+use peer_stats::timerange::{backfill_ranges, last_days, parse_time_range};
+use peer_stats::watch::WatchCursor;
+use peer_stats::{
+    parse_rib_file_types, parse_rib_with_updates, parse_updates_files, As2Rel, Codec,
+    CollectorFilter, DumpItem, OutputFormat, OutputOptions, PathLayout, Prefix2As, ProjectMapping,
+    RibDataType, RibOptions, RibResults, SelectionPolicy, Tier1Config, UpdatesBaseline,
+};
 use rayon::prelude::*;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use std::{fs, thread};
 use tracing::{error, info, Level};
 
@@ -23,6 +45,10 @@ struct Opts {
     #[clap(long)]
     debug: bool,
 
+    /// TOML config file with default settings, overridden by flags and environment variables
+    #[clap(long)]
+    config: Option<PathBuf>,
+
     /// whether to dry run the code
     #[clap(long)]
     dry_run: bool,
@@ -31,101 +57,974 @@ struct Opts {
     #[clap(long)]
     force: bool,
 
-    /// whether to do only daily parsing
-    #[clap(long)]
+    /// whether to do only daily parsing, same as `--select first-per-day`
+    #[clap(long, conflicts_with = "select")]
     only_daily: bool,
 
-    /// start timestamp
+    /// which RIB dumps of each collector and day to process: all, first-per-day,
+    /// closest-to-HH:MM or every-N-hours, choosing the nearest dump of the day if none exists at
+    /// the target time
+    #[clap(long, default_value = "all")]
+    select: SelectionPolicy,
+
+    /// start time: unix timestamp, RFC 3339 time, `YYYY-MM-DD` date, `now`, `today`, `yesterday`,
+    /// or a relative time such as `-3d` or `today-12h`
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        required_unless_present_any = ["reconstruct_at", "report", "backfill_months", "last", "watch", "config"]
+    )]
+    ts_start: Option<String>,
+
+    /// end time in the same forms as the start time, now by default
+    #[clap(long, allow_hyphen_values = true)]
+    ts_end: Option<String>,
+
+    /// process the given number of days before today until now, e.g. for a daily cron job
+    #[clap(long, conflicts_with_all = ["ts_start", "ts_end", "backfill_months", "reconstruct_at"])]
+    last: Option<u32>,
+
+    /// process the given number of months before the current one, one month at a time starting
+    /// with the most recent
+    #[clap(long, conflicts_with_all = ["ts_start", "ts_end", "reconstruct_at"])]
+    backfill_months: Option<u32>,
+
+    /// collectors to process, comma-separated or repeated, with `*` and `?` wildcards, e.g.
+    /// `rrc*,route-views2`; all by default
+    #[clap(long, value_delimiter = ',')]
+    collector: Vec<String>,
+
+    /// collectors to skip, comma-separated or repeated, with `*` and `?` wildcards
+    #[clap(long, value_delimiter = ',')]
+    exclude_collector: Vec<String>,
+
+    /// projects to process, e.g. `riperis` or `route-views`, comma-separated or repeated
+    #[clap(long, value_delimiter = ',')]
+    project: Vec<String>,
+
+    /// Output directory
+    #[clap(long, required_unless_present = "config")]
+    output_dir: Option<PathBuf>,
+
+    /// URL of the BGPKIT broker instance to query
+    #[clap(long, env = "BROKER_URL")]
+    broker_url: Option<String>,
+
+    /// Path to a local mirror of the RouteViews and RIS archives to read dump files from,
+    /// instead of querying the broker
     #[clap(long)]
-    ts_start: String,
+    local_archive: Option<PathBuf>,
 
-    /// end timestamp
+    /// file mapping collectors to projects, one `<collector> <project>` pair per line, for
+    /// collectors not following the RIS and RouteViews naming
     #[clap(long)]
-    ts_end: String,
+    project_mapping: Option<PathBuf>,
+
+    /// number of retries of a dump file after transient download or truncation failures
+    #[clap(long, default_value_t = 2)]
+    retries: u32,
 
-    /// specify collector
+    /// wait time before the first retry in seconds, doubled for every further retry
+    #[clap(long, default_value_t = 10)]
+    retry_backoff: u64,
+
+    /// wall-clock timeout for processing a single dump file in minutes
     #[clap(long)]
-    collector: Option<String>,
+    timeout: Option<u64>,
 
-    /// Output directory
+    /// comma-separated list of data types to produce from RIB dumps (peer-stats, pfx2as, as2rel,
+    /// as2rel-v4, as2rel-v6), all by default
+    #[clap(long, value_delimiter = ',')]
+    types: Vec<RibDataType>,
+
+    /// file format of the peer-stats, pfx2as and as2rel data files: json or ndjson (both
+    /// bzip2-compressed), or parquet
+    #[clap(long, default_value = "json")]
+    format: OutputFormat,
+
+    /// compression of the data files: bz2, gz, zst or none; bz2 by default, zst for parquet
+    #[clap(long)]
+    compression: Option<Codec>,
+
+    /// compression level, 9 for bz2, 6 for gz and 3 for zst by default
+    #[clap(long)]
+    compression_level: Option<u32>,
+
+    /// layout of the data file paths under the output directory: `default`
+    /// (`{type_dir}/{collector}/{YYYY}/{MM}/{type}_{collector}_{date}_{ts}.{ext}`), `hive`
+    /// (`{type_dir}/date={date}/collector={collector}/...`) or a custom template
+    #[clap(long, default_value = "default")]
+    layout: PathLayout,
+
+    /// maximum number of processing threads, capped at the number of CPUs
+    #[clap(long, env = "MAX_THREADS")]
+    max_threads: Option<usize>,
+
+    /// memory budget for concurrently processed RIB dumps, e.g. `48G`; each dump is estimated
+    /// from its file size and waits until enough of the budget is free
+    #[clap(long, value_parser = parse_size)]
+    max_memory: Option<u64>,
+
+    /// exit with a non-zero status if more than this share of the processed RIB dumps failed
+    #[clap(long, default_value_t = 0.5, value_parser = parse_failure_ratio)]
+    max_failure_ratio: f64,
+
+    /// write the run summary as Prometheus metrics to this file, e.g. in the node_exporter
+    /// textfile collector directory
+    #[clap(long)]
+    prometheus_file: Option<PathBuf>,
+
+    /// read back and parse every written data file before moving it into place
+    #[clap(long)]
+    verify: bool,
+
+    /// retry items that failed or never finished according to the run manifest, even if their
+    /// output files exist
+    #[clap(long)]
+    resume: bool,
+
+    /// print a summary of the run manifest in the output directory and exit
+    #[clap(long)]
+    report: bool,
+
+    /// process BGP UPDATES dumps instead of RIB dumps
+    #[clap(long)]
+    updates: bool,
+
+    /// length of the time windows UPDATES dumps are grouped into, in minutes
+    #[clap(long, default_value_t = 60, value_parser = clap::value_parser!(i64).range(1..))]
+    updates_window: i64,
+
+    /// keep running and process new RIB dumps as they appear, polling the broker or the local
+    /// archive, with the position persisted in the output directory
+    #[clap(
+        long,
+        conflicts_with_all = ["ts_start", "ts_end", "last", "backfill_months", "reconstruct_at", "updates", "dry_run", "report"]
+    )]
+    watch: bool,
+
+    /// time between polls in watch mode, in minutes
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    poll_interval: u64,
+
+    /// how far back watch mode looks for dumps that appear late, in hours, rounded down to midnight
+    #[clap(long, default_value_t = 72, value_parser = clap::value_parser!(i64).range(0..))]
+    watch_lookback: i64,
+
+    /// command run with `sh -c` in watch mode for each day with new RIB dumps, after they are
+    /// processed, with `{date}` and `{output_dir}` replaced; repeat for several indexers
     #[clap(long)]
-    output_dir: PathBuf,
+    index_command: Vec<String>,
+
+    /// reconstruct the RIB of every collector at the given unix timestamp from its latest RIB dump
+    /// and the subsequent UPDATES dumps, instead of processing RIB dumps as they are
+    #[clap(long, conflicts_with = "updates")]
+    reconstruct_at: Option<i64>,
+
+    /// how far back to look for the latest RIB dump before the reconstruction time, in hours
+    #[clap(long, default_value_t = 8)]
+    reconstruct_lookback: i64,
+
+    /// tier-1 ASes for as2rel inference, only configurable in the config file
+    #[clap(skip)]
+    tier1: Tier1Config,
+
+    /// projects of the collectors, loaded from `--project-mapping` in main
+    #[clap(skip)]
+    mapping: ProjectMapping,
+}
+
+impl Opts {
+    /// The output directory, checked to be set after merging the config file.
+    fn output_dir(&self) -> &Path {
+        self.output_dir
+            .as_deref()
+            .expect("output directory is checked in main")
+    }
+}
+
+const MANIFEST_FILE: &str = "manifest.jsonl";
+const WATCH_CURSOR_FILE: &str = "watch-cursor.json";
+/// Polls a failing dump is processed in by watch mode before it is given up.
+const WATCH_MAX_ATTEMPTS: u32 = 3;
+
+/// The data types selected with `--types`, all if none are given.
+fn parse_failure_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        Ok(_) => Err(format!("{} is not between 0 and 1", s)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn get_rib_types(opts: &Opts) -> Vec<RibDataType> {
+    match opts.types.is_empty() {
+        true => RibDataType::ALL.to_vec(),
+        false => opts.types.clone(),
+    }
+}
+
+/// Processing options for the given data types with the configured tier-1 ASes.
+fn get_rib_options(opts: &Opts, types: Vec<RibDataType>) -> RibOptions {
+    RibOptions {
+        types,
+        tier1: opts.tier1.clone(),
+    }
 }
 
-fn write_results(output_path: &str, data: &Value) {
-    let file = match File::create(output_path) {
-        Err(_why) => panic!("couldn't open {}", output_path),
-        Ok(file) => file,
+/// Fill in the settings given neither as flag nor as environment variable from the config file.
+fn apply_config(opts: &mut Opts, matches: &ArgMatches, config: BootstrapConfig) {
+    let unset = |id: &str| {
+        !matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
     };
+    let BootstrapConfig {
+        source,
+        collectors,
+        time,
+        output,
+        limits,
+        watch,
+        tier1,
+    } = config;
+
+    opts.broker_url = opts.broker_url.take().or(source.broker_url);
+    opts.local_archive = opts.local_archive.take().or(source.local_archive);
+    opts.project_mapping = opts.project_mapping.take().or(source.project_mapping);
+
+    if opts.collector.is_empty() {
+        opts.collector = collectors.include;
+    }
+    if opts.exclude_collector.is_empty() {
+        opts.exclude_collector = collectors.exclude;
+    }
+    if opts.project.is_empty() {
+        opts.project = collectors.projects;
+    }
+
+    // a time range given as flags replaces the one of the config file as a whole
+    if opts.ts_start.is_none()
+        && opts.ts_end.is_none()
+        && opts.backfill_months.is_none()
+        && opts.last.is_none()
+        && opts.reconstruct_at.is_none()
+    {
+        match (time.backfill_months, time.last_days) {
+            (Some(months), _) => opts.backfill_months = Some(months),
+            (None, Some(days)) => opts.last = Some(days),
+            (None, None) => (opts.ts_start, opts.ts_end) = (time.start, time.end),
+        }
+    }
+    if let (true, Some(select)) = (unset("select"), time.select) {
+        opts.select = select;
+    }
+
+    opts.output_dir = opts.output_dir.take().or(output.dir);
+    if let (true, Some(layout)) = (unset("layout"), output.layout) {
+        opts.layout = layout;
+    }
+    if let (true, Some(format)) = (unset("format"), output.format) {
+        opts.format = format;
+    }
+    opts.compression = opts.compression.or(output.compression);
+    opts.compression_level = opts.compression_level.or(output.compression_level);
+    if opts.types.is_empty() {
+        opts.types = output.types;
+    }
+
+    opts.max_threads = opts.max_threads.or(limits.threads);
+    opts.max_memory = opts.max_memory.or(limits.max_memory);
+    if let (true, Some(retries)) = (unset("retries"), limits.retries) {
+        opts.retries = retries;
+    }
+    if let (true, Some(backoff)) = (unset("retry_backoff"), limits.retry_backoff) {
+        opts.retry_backoff = backoff;
+    }
+    opts.timeout = opts.timeout.or(limits.timeout);
+    if let (true, Some(ratio)) = (unset("max_failure_ratio"), limits.max_failure_ratio) {
+        opts.max_failure_ratio = ratio;
+    }
+
+    if let (true, Some(interval)) = (unset("poll_interval"), watch.poll_interval) {
+        opts.poll_interval = interval;
+    }
+    if let (true, Some(lookback)) = (unset("watch_lookback"), watch.lookback) {
+        opts.watch_lookback = lookback;
+    }
+    if opts.index_command.is_empty() {
+        opts.index_command = watch.index_commands;
+    }
 
-    let compressor = BzEncoder::new(file, Compression::best());
-    let mut writer = BufWriter::with_capacity(128 * 1024, compressor);
+    opts.tier1 = tier1;
+}
 
-    let _ = writer.write_all(serde_json::to_string_pretty(data).unwrap().as_ref());
+/// The collectors and projects selected with `--collector`, `--exclude-collector` and `--project`.
+fn get_collector_filter(opts: &Opts) -> CollectorFilter {
+    CollectorFilter::new(
+        opts.collector.clone(),
+        opts.exclude_collector.clone(),
+        opts.project.clone(),
+    )
 }
 
-fn main() {
-    let opts = Opts::parse();
+fn get_retry_policy(opts: &Opts) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: opts.retries.saturating_add(1),
+        initial_backoff: Duration::from_secs(opts.retry_backoff),
+        timeout: opts
+            .timeout
+            .map(|t| Duration::from_secs(t.saturating_mul(60))),
+        ..Default::default()
+    }
+}
 
-    if opts.debug {
-        tracing_subscriber::fmt()
-            // filter spans/events with level TRACE or higher.
-            .with_max_level(Level::INFO)
-            .with_writer(std::io::stderr)
-            .init();
+fn manifest_record(
+    item: &DumpItem,
+    status: ItemStatus,
+    started_at: i64,
+    error: Option<String>,
+    outputs: Vec<ManifestOutput>,
+) -> ManifestRecord {
+    ManifestRecord {
+        url: item.url.clone(),
+        collector: item.collector_id.clone(),
+        timestamp: item.ts_start.and_utc().timestamp(),
+        status,
+        started_at,
+        finished_at: match status {
+            ItemStatus::Started => None,
+            _ => Some(Utc::now().timestamp()),
+        },
+        error,
+        outputs,
     }
+}
 
-    let mut num_threads = num_cpus::get();
-    if let Ok(v) = std::env::var("MAX_THREADS") {
-        if let Ok(t) = v.parse::<usize>() {
-            if t <= num_threads {
-                // ensure the number of threads we use does not exceed the maximum threads count
-                num_threads = t;
+/// Write the results of each data type to its output path.
+fn write_rib_results(
+    file_path_map: &BTreeMap<RibDataType, String>,
+    results: &RibResults,
+    ts: &NaiveDateTime,
+    options: &OutputOptions,
+    verify: bool,
+) -> Result<()> {
+    let date = ts.format("%Y-%m-%d").to_string();
+    for (data_type, output_path) in file_path_map {
+        write_atomic(output_path.as_str(), options, verify, |file| {
+            results.write_data(*data_type, options, &date, file)
+        })?;
+    }
+    Ok(())
+}
+
+/// Collect the output paths of the selected data types, leaving out existing files unless
+/// `overwrite` is set.
+fn get_missing_outputs(
+    output_dir: &str,
+    layout: &PathLayout,
+    types: &[RibDataType],
+    options: &OutputOptions,
+    collector_id: &str,
+    ts: &NaiveDateTime,
+    overwrite: bool,
+) -> BTreeMap<RibDataType, String> {
+    types
+        .iter()
+        .filter_map(|data_type| {
+            let output_path = layout.format(
+                output_dir,
+                data_type.as_str(),
+                collector_id,
+                ts,
+                options.extension().as_str(),
+            );
+            match overwrite || !std::path::Path::new(output_path.as_str()).exists() {
+                true => Some((*data_type, output_path)),
+                false => None,
             }
+        })
+        .collect()
+}
+
+/// Find the latest RIB-based data file of the same day at or before the given timestamp, in any
+/// output format, among the files in the layout directory of that day.
+fn find_rib_artifact(
+    output_dir: &str,
+    layout: &PathLayout,
+    data_type: &str,
+    collector_id: &str,
+    ts: &NaiveDateTime,
+) -> Option<String> {
+    let path = layout.format(output_dir, data_type, collector_id, ts, "bz2");
+    let file_dir = std::path::Path::new(path.as_str()).parent()?;
+    let timestamp = ts.and_utc().timestamp();
+
+    fs::read_dir(file_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let path = entry.path().to_str()?.to_string();
+            let file = layout.parse(path.as_str())?;
+            match file.data_type == data_type
+                && file.collector == collector_id
+                && file.date == ts.date()
+                && file.ts <= timestamp
+            {
+                true => Some((file.ts, path)),
+                false => None,
+            }
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Load the (prefix, origin) pairs and AS links known from the RIB dump processed for the day.
+fn load_updates_baseline(
+    output_dir: &str,
+    layout: &PathLayout,
+    collector_id: &str,
+    ts: &NaiveDateTime,
+) -> UpdatesBaseline {
+    let pfx2as = find_rib_artifact(output_dir, layout, "pfx2as", collector_id, ts)
+        .and_then(|p| read_data_file::<Prefix2As>(p.as_str()).ok());
+    let as2rel = find_rib_artifact(output_dir, layout, "as2rel", collector_id, ts)
+        .and_then(|p| read_data_file::<As2Rel>(p.as_str()).ok());
+    match (pfx2as, as2rel) {
+        (Some(pfx2as), Some(as2rel)) => UpdatesBaseline::from_pfx2as_as2rel(&pfx2as, &as2rel),
+        _ => {
+            info!(
+                "no RIB data found for {} at {}, reporting all observed pairs and links",
+                collector_id, ts
+            );
+            UpdatesBaseline::new()
         }
     }
+}
 
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(num_threads)
-        .build_global()
-        .unwrap();
+/// Group UPDATES dump items into per-collector time windows and process each window, returning
+/// the summary of the processed windows.
+fn process_updates(opts: &Opts, options: &OutputOptions, items: Vec<DumpItem>) -> RunSummary {
+    let window = opts.updates_window * 60;
+    let mut windows: BTreeMap<(String, i64), Vec<DumpItem>> = BTreeMap::new();
+    for item in items {
+        let ts = item.ts_start.and_utc().timestamp();
+        windows
+            .entry((item.collector_id.clone(), ts - ts.rem_euclid(window)))
+            .or_default()
+            .push(item);
+    }
+    let windows: Vec<((String, i64), Vec<DumpItem>)> = windows.into_iter().collect();
+    info!("total of {} UPDATES windows to process", windows.len());
 
-    info!("using maximum {} threads for processing.", num_threads);
+    let output_dir = opts.output_dir().to_str().unwrap();
+    let layout = &opts.layout;
+    let retry_policy = get_retry_policy(opts);
+    // UPDATES results are always JSON
+    let options = &OutputOptions {
+        format: OutputFormat::Json,
+        ..*options
+    };
+    let extension = options.extension();
+    let summary = Mutex::new(RunSummary::new(windows.len()));
+    let run_start = Instant::now();
+
+    windows
+        .par_iter()
+        .for_each(|((collector_id, window_start), items)| {
+            let ts = DateTime::from_timestamp(*window_start, 0)
+                .unwrap()
+                .naive_utc();
+            let stats_path =
+                layout.format(output_dir, "updates-stats", collector_id, &ts, &extension);
+            let new_path = layout.format(output_dir, "updates-new", collector_id, &ts, &extension);
+            if !opts.force
+                && std::path::Path::new(stats_path.as_str()).exists()
+                && std::path::Path::new(new_path.as_str()).exists()
+            {
+                info!(
+                    "result files for {} window {} already exist, skip processing",
+                    collector_id, window_start
+                );
+                summary.lock().unwrap().record(
+                    collector_id,
+                    ItemStatus::Skipped,
+                    Duration::ZERO,
+                    0,
+                    0,
+                );
+                return;
+            }
+
+            let project = items[0].project.clone();
+            let urls: Vec<String> = items.iter().map(|i| i.url.clone()).collect();
+            let baseline = load_updates_baseline(output_dir, layout, collector_id, &ts);
+            let window_start_time = Instant::now();
+            let bytes_read = items.iter().map(|i| i.rough_size.max(0) as u64).sum();
+
+            info!(
+                "start parsing {} UPDATES files of {} window {}",
+                urls.len(),
+                collector_id,
+                window_start
+            );
+            let (ts_start, ts_end) = (*window_start, *window_start + window);
+            let task_collector = collector_id.clone();
+            let verify = opts.verify;
+            let result = retry_policy
+                .run(
+                    format!("UPDATES window {} of {}", window_start, collector_id).as_str(),
+                    move |cancellation| {
+                        parse_updates_files(
+                            &urls,
+                            project.as_str(),
+                            task_collector.as_str(),
+                            ts_start,
+                            ts_end,
+                            baseline.clone(),
+                            cancellation,
+                        )
+                    },
+                )
+                .and_then(|(updates_stats, updates_new)| {
+                    let elems_count = updates_stats
+                        .peers
+                        .values()
+                        .map(|p| (p.num_announcements + p.num_withdrawals) as u64)
+                        .sum();
+                    write_results(stats_path.as_str(), &json!(updates_stats), options, verify)
+                        .and_then(|_| {
+                            write_results(new_path.as_str(), &json!(updates_new), options, verify)
+                        })
+                        .map(|_| elems_count)
+                        .map_err(|e| (FailureKind::Write, e))
+                });
+
+            let (status, elems_count) = match result {
+                Ok(elems_count) => {
+                    info!(
+                        "processing UPDATES window {} of {} finished",
+                        window_start, collector_id
+                    );
+                    (ItemStatus::Succeeded, elems_count)
+                }
+                Err((kind, e)) => {
+                    error!(
+                        "processing of UPDATES window {} of {} failed ({}): {:#}",
+                        window_start, collector_id, kind, e
+                    );
+                    (ItemStatus::Failed, 0)
+                }
+            };
+            summary.lock().unwrap().record(
+                collector_id,
+                status,
+                window_start_time.elapsed(),
+                bytes_read,
+                elems_count,
+            );
+        });
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.duration_secs = run_start.elapsed().as_secs_f64();
+    summary
+}
+
+/// Collect the dump files of a data type (`rib` or `updates`), or of both if `None`, within
+/// `[ts_start, ts_end]`, either from the local archive mirror or from the broker.
+fn query_dump_items(
+    opts: &Opts,
+    data_type: Option<&str>,
+    ts_start: &NaiveDateTime,
+    ts_end: &NaiveDateTime,
+    filter: &CollectorFilter,
+) -> Result<Vec<DumpItem>> {
+    let mapping = &opts.mapping;
+    if let Some(archive_dir) = &opts.local_archive {
+        let items = scan_local_archive(archive_dir, data_type, ts_start, ts_end)?;
+        return Ok(items
+            .into_iter()
+            .map(|mut item| {
+                mapping.apply(&mut item);
+                item
+            })
+            .filter(|item| filter.matches(item))
+            .collect());
+    }
 
-    info!("start querying broker for available RIB dump files.");
     let mut broker = BgpkitBroker::new()
-        .ts_start(opts.ts_start.as_str())
-        .ts_end(opts.ts_end.as_str())
-        .data_type("rib")
+        .ts_start(ts_start.and_utc().timestamp().to_string().as_str())
+        .ts_end(ts_end.and_utc().timestamp().to_string().as_str())
         .page_size(1000);
-    if let Ok(url) = std::env::var("BROKER_URL") {
+    if let Some(data_type) = data_type {
+        broker = broker.data_type(data_type);
+    }
+    if let Some(url) = &opts.broker_url {
         broker = broker.broker_url(url.as_str());
     }
-    if let Some(c) = opts.collector {
-        broker = broker.collector_id(c.as_str());
+    if let Some(c) = filter.single_collector() {
+        broker = broker.collector_id(c);
     }
-
-    let items: Vec<BrokerItem> = broker
-        .query()
-        .unwrap()
+    Ok(broker
+        .query()?
         .into_iter()
-        .filter(|item| {
-            if !opts.only_daily {
-                return true;
-            }
-            // only process the first one per-day
-            item.ts_start.hour() == 0
+        .map(|broker_item| {
+            let mut item = DumpItem::from(broker_item);
+            mapping.apply(&mut item);
+            item
         })
-        .collect();
+        .filter(|item| filter.matches(item))
+        .collect())
+}
+
+/// Write a data file to the output path atomically, see [write_atomic_checked]. With `verify`,
+/// the temporary file is read and parsed again before it is moved into place.
+fn write_atomic<F>(output_path: &str, options: &OutputOptions, verify: bool, write: F) -> Result<()>
+where
+    F: FnOnce(File) -> Result<File>,
+{
+    write_atomic_checked(Path::new(output_path), write, |tmp_path| match verify {
+        true => verify_results(tmp_path.to_str().unwrap(), options),
+        false => Ok(()),
+    })
+}
+
+/// Write compressed JSON data to the output path.
+fn write_results(
+    output_path: &str,
+    data: &Value,
+    options: &OutputOptions,
+    verify: bool,
+) -> Result<()> {
+    write_atomic(output_path, options, verify, |file| {
+        let compressor = CompressedWriter::new(file, options.codec, options.level)?;
+        let mut writer = BufWriter::with_capacity(128 * 1024, compressor);
+        serde_json::to_writer_pretty(&mut writer, data)?;
+        writer.into_inner().map_err(|e| e.into_error())?.finish()
+    })
+}
+
+/// Read back a written data file to make sure it is complete and valid.
+fn verify_results(file_path: &str, options: &OutputOptions) -> Result<()> {
+    let result = match options.format {
+        OutputFormat::Json => {
+            let reader = decompressed_reader(File::open(file_path)?, options.codec)?;
+            serde_json::from_reader::<_, serde::de::IgnoredAny>(BufReader::new(reader))
+                .map(|_| ())
+                .map_err(anyhow::Error::from)
+        }
+        OutputFormat::Ndjson => {
+            let reader = decompressed_reader(File::open(file_path)?, options.codec)?;
+            BufReader::new(reader).lines().try_for_each(|line| {
+                serde_json::from_str::<serde::de::IgnoredAny>(line?.as_str())?;
+                Ok(())
+            })
+        }
+        OutputFormat::Parquet => verify_parquet(File::open(file_path)?),
+    };
+    result.with_context(|| format!("verification of {} failed", file_path))
+}
+
+/// Reconstruct the RIB of every collector at the target timestamp and process the results.
+///
+/// For each collector, the latest RIB dump within the lookback period is replayed forward with
+/// the UPDATES dumps up to the target time, so that all collectors produce snapshots aligned at
+/// the same instant. Results are written with the target timestamp. Returns the summary of the
+/// reconstructed collectors, or `None` for dry runs.
+fn process_reconstruct(opts: &Opts, options: &OutputOptions, target_ts: i64) -> Option<RunSummary> {
+    let target = DateTime::from_timestamp(target_ts, 0).unwrap().naive_utc();
+    let lookback_start = target - chrono::Duration::hours(opts.reconstruct_lookback);
+    // RIB and UPDATES dumps of all collectors in a single query, as every query of a local
+    // archive walks the whole mirror
+    let items = match query_dump_items(
+        opts,
+        None,
+        &lookback_start,
+        &target,
+        &get_collector_filter(opts),
+    ) {
+        Ok(items) => items,
+        Err(e) => {
+            error!("querying dump files failed: {:#}", e);
+            return Some(RunSummary::failed_query());
+        }
+    };
+
+    // latest RIB dump per collector at or before the target time, and all UPDATES dumps per
+    // collector
+    let mut latest_ribs: HashMap<String, DumpItem> = HashMap::new();
+    let mut collector_updates: HashMap<String, Vec<DumpItem>> = HashMap::new();
+    for item in items {
+        if item.ts_start.and_utc().timestamp() > target_ts {
+            continue;
+        }
+        match item.data_type.as_str() {
+            "rib" => match latest_ribs.get(&item.collector_id) {
+                Some(latest) if latest.ts_start >= item.ts_start => {}
+                _ => {
+                    latest_ribs.insert(item.collector_id.clone(), item);
+                }
+            },
+            "updates" => collector_updates
+                .entry(item.collector_id.clone())
+                .or_default()
+                .push(item),
+            _ => {}
+        }
+    }
+    let rib_items: Vec<DumpItem> = latest_ribs.into_values().collect();
+    info!(
+        "reconstructing RIBs of {} collectors at {}",
+        rib_items.len(),
+        target_ts
+    );
+    if opts.dry_run {
+        for item in &rib_items {
+            info!("{} starts from {}", item.collector_id, item.url);
+        }
+        return None;
+    }
+
+    let output_dir = opts.output_dir().to_str().unwrap();
+    let ts = target;
+    let retry_policy = get_retry_policy(opts);
+    let rib_types = get_rib_types(opts);
+    let budget = opts.max_memory.map(MemoryBudget::new);
+    let mut rib_items = rib_items;
+    rib_items.sort_by_key(|item| std::cmp::Reverse(item.rough_size));
+    let summary = Mutex::new(RunSummary::new(rib_items.len()));
+    let run_start = Instant::now();
+
+    rib_items.iter().par_bridge().for_each(|item| {
+        let file_path_map = get_missing_outputs(
+            output_dir,
+            &opts.layout,
+            &rib_types,
+            options,
+            &item.collector_id,
+            &ts,
+            opts.force,
+        );
+        if file_path_map.is_empty() {
+            info!(
+                "result files of {} already exist, skip processing",
+                item.collector_id.as_str()
+            );
+            summary.lock().unwrap().record(
+                &item.collector_id,
+                ItemStatus::Skipped,
+                Duration::ZERO,
+                0,
+                0,
+            );
+            return;
+        }
+        let rib_options = get_rib_options(opts, file_path_map.keys().copied().collect());
+
+        let rib_ts = item.ts_start.and_utc().timestamp();
+        let mut updates_items: Vec<&DumpItem> = collector_updates
+            .get(&item.collector_id)
+            .into_iter()
+            .flatten()
+            .filter(|i| i.ts_start >= item.ts_start)
+            .collect();
+        updates_items.sort_by_key(|i| i.ts_start);
+        let updates_urls: Vec<String> = updates_items.iter().map(|i| i.url.clone()).collect();
+        let bytes_read = [item]
+            .into_iter()
+            .chain(updates_items)
+            .map(|i| i.rough_size.max(0) as u64)
+            .sum();
+
+        info!(
+            "start reconstructing {} from {} and {} UPDATES files",
+            item.collector_id.as_str(),
+            item.url.as_str(),
+            updates_urls.len()
+        );
+        // held until all attempts have exited, as timed-out attempts are waited for
+        let _permit = budget.as_ref().map(|b| b.acquire(estimate_memory(item)));
+        let item_start = Instant::now();
+        let task_item = item.clone();
+        let result = retry_policy
+            .run(
+                format!("reconstruction of {}", item.collector_id).as_str(),
+                move |cancellation| {
+                    parse_rib_with_updates(
+                        task_item.url.as_str(),
+                        rib_ts,
+                        &updates_urls,
+                        target_ts,
+                        task_item.project.as_str(),
+                        task_item.collector_id.as_str(),
+                        &rib_options,
+                        cancellation,
+                    )
+                },
+            )
+            .and_then(|results| {
+                write_rib_results(&file_path_map, &results, &ts, options, opts.verify)
+                    .map(|_| results.elems_count)
+                    .map_err(|e| (FailureKind::Write, e))
+            });
+        let (status, elems_count) = match result {
+            Ok(elems_count) => {
+                info!("reconstructing {} finished", item.collector_id.as_str());
+                (ItemStatus::Succeeded, elems_count)
+            }
+            Err((kind, e)) => {
+                error!(
+                    "reconstruction of {} failed ({}): {:#}",
+                    item.collector_id.as_str(),
+                    kind,
+                    e
+                );
+                (ItemStatus::Failed, 0)
+            }
+        };
+        summary.lock().unwrap().record(
+            &item.collector_id,
+            status,
+            item_start.elapsed(),
+            bytes_read,
+            elems_count,
+        );
+    });
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.duration_secs = run_start.elapsed().as_secs_f64();
+    Some(summary)
+}
+
+/// Report the dump chosen for each collector and day, as JSON on stdout for dry runs.
+fn log_selection(selections: &[Selection], dry_run: bool) {
+    let report = selection_report(selections);
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+    for (collector, days) in &report {
+        for (date, dumps) in days {
+            for dump in dumps {
+                info!(
+                    "{} {}: selected {} for target {}",
+                    collector,
+                    date,
+                    dump.url,
+                    dump.target.as_deref().unwrap_or("first")
+                );
+            }
+        }
+    }
+}
+
+/// Query, select and process the dump files of a time range, returning the summary of the
+/// processed RIB dumps or UPDATES windows, or `None` for dry runs. A failed query is logged and
+/// counted in the summary.
+fn process_range(
+    opts: &Opts,
+    options: &OutputOptions,
+    ts_start: &NaiveDateTime,
+    ts_end: &NaiveDateTime,
+) -> Option<RunSummary> {
+    info!("start querying available dump files.");
+    let items: Vec<DumpItem> = match query_dump_items(
+        opts,
+        match opts.updates {
+            true => Some("updates"),
+            false => Some("rib"),
+        },
+        ts_start,
+        ts_end,
+        &get_collector_filter(opts),
+    ) {
+        Ok(items) => items,
+        Err(e) => {
+            error!(
+                "querying dump files from {} to {} failed: {:#}",
+                ts_start, ts_end, e
+            );
+            return Some(RunSummary::failed_query());
+        }
+    };
+    let items = match opts.updates {
+        true => items,
+        false => select_rib_items(opts, items),
+    };
     let total_items = items.len();
 
     if opts.dry_run {
         info!("total of {total_items} RIB dump files to process");
-        info!("first RIB is {}", items.first().unwrap().url);
-        info!("last RIB is {}", items.last().unwrap().url);
-        return;
+        if let (Some(first), Some(last)) = (items.first(), items.last()) {
+            info!("first RIB is {}", first.url);
+            info!("last RIB is {}", last.url);
+        }
+        return None;
+    }
+
+    if opts.updates {
+        return Some(process_updates(opts, options, items));
+    }
+
+    Some(process_ribs(opts, options, items).0)
+}
+
+/// Process the time range given with `--ts-start` and `--ts-end`, `--last` or
+/// `--backfill-months`, one range after the other, and merge their summaries.
+fn process_ranges(opts: &Opts, options: &OutputOptions) -> Option<RunSummary> {
+    let now = Utc::now().naive_utc();
+    let ranges = match (opts.backfill_months, opts.last) {
+        (Some(months), _) => backfill_ranges(now.date(), months),
+        (None, Some(days)) => vec![last_days(days, now)],
+        (None, None) => {
+            let start = opts.ts_start.as_deref().unwrap();
+            let end = opts.ts_end.as_deref().unwrap_or("now");
+            match parse_time_range(start, end, now) {
+                Ok(range) => vec![range],
+                Err(e) => Opts::command().error(ErrorKind::ValueValidation, e).exit(),
+            }
+        }
+    };
+    let mut summary: Option<RunSummary> = None;
+    for (ts_start, ts_end) in &ranges {
+        info!("processing dump files from {} to {}", ts_start, ts_end);
+        if let Some(range_summary) = process_range(opts, options, ts_start, ts_end) {
+            match summary.as_mut() {
+                Some(s) => s.merge(range_summary),
+                None => summary = Some(range_summary),
+            }
+        }
     }
+    summary
+}
 
+/// The selection policy given with `--select` or `--only-daily`.
+fn get_selection_policy(opts: &Opts) -> SelectionPolicy {
+    match opts.only_daily {
+        true => SelectionPolicy::FirstPerDay,
+        false => opts.select,
+    }
+}
+
+/// Choose the RIB dumps to process with the selection policy.
+fn select_rib_items(opts: &Opts, items: Vec<DumpItem>) -> Vec<DumpItem> {
+    let policy = get_selection_policy(opts);
+    let selections = policy.select(items);
+    if policy != SelectionPolicy::All {
+        log_selection(&selections, opts.dry_run);
+    }
+    selections.into_iter().map(|s| s.item).collect()
+}
+
+/// Process RIB dumps in parallel, recording each in the run manifest, and summarize the results.
+/// Also returns the status of each dump by URL.
+fn process_ribs(
+    opts: &Opts,
+    options: &OutputOptions,
+    mut items: Vec<DumpItem>,
+) -> (RunSummary, BTreeMap<String, ItemStatus>) {
+    let total_items = items.len();
     let (sender_pb, receiver_pb) = channel::<String>();
 
     // dedicated thread for showing progress of the parsing
@@ -141,103 +1040,536 @@ fn main() {
         }
     });
 
-    let output_dir = opts.output_dir.to_str().unwrap();
-
-    let data_types = ["peer-stats", "pfx2as", "as2rel", "as2rel-v4", "as2rel-v6"];
-
-    items.par_iter().for_each_with(sender_pb, |s1, item| {
-        let ts = item.ts_start;
-        let timestamp = ts.and_utc().timestamp();
-
-        let mut file_path_map: HashMap<String, String> = HashMap::new();
-        for data_type in data_types {
-            let file_dir = match data_type.starts_with("as2rel") {
-                true => format!(
-                    "{}/{}/{}/{:02}/{:02}",
-                    output_dir,
-                    "as2rel",
-                    &item.collector_id,
-                    ts.year(),
-                    ts.month()
-                ),
-                false => format!(
-                    "{}/{}/{}/{:02}/{:02}",
-                    output_dir,
-                    data_type,
-                    &item.collector_id,
-                    ts.year(),
-                    ts.month()
-                ),
-            };
-            fs::create_dir_all(file_dir.as_str()).unwrap();
-            let output_path = format!(
-                "{}/{}_{}_{}-{:02}-{:02}_{}.bz2",
-                &file_dir,
-                data_type,
+    let output_dir = opts.output_dir().to_str().unwrap();
+    fs::create_dir_all(output_dir).unwrap();
+    let manifest_path = opts.output_dir().join(MANIFEST_FILE);
+    let previous_records = RunManifest::load_latest(&manifest_path).unwrap();
+    let manifest = RunManifest::open(&manifest_path).unwrap();
+    let retry_policy = get_retry_policy(opts);
+    let rib_types = get_rib_types(opts);
+    let budget = opts.max_memory.map(MemoryBudget::new);
+    let summary = Mutex::new(RunSummary::new(total_items));
+    let statuses = Mutex::new(BTreeMap::new());
+    let record_status = |item: &DumpItem, status, duration, bytes_read, elems_count| {
+        summary.lock().unwrap().record(
+            &item.collector_id,
+            status,
+            duration,
+            bytes_read,
+            elems_count,
+        );
+        statuses.lock().unwrap().insert(item.url.clone(), status);
+    };
+    let run_start = Instant::now();
+    info!("recording run manifest at {}", manifest.path().display());
+
+    // largest dumps first, handed out in order, to avoid a long tail of large collectors
+    items.sort_by_key(|item| std::cmp::Reverse(item.rough_size));
+    items
+        .iter()
+        .par_bridge()
+        .for_each_with(sender_pb, |s1, item| {
+            let ts = item.ts_start;
+            let timestamp = ts.and_utc().timestamp();
+            let started_at = Utc::now().timestamp();
+
+            let previous_status = previous_records.get(&item.url).map(|r| r.status);
+            // with --resume, failed and unfinished items are redone regardless of existing files
+            let retry = opts.resume
+                && matches!(
+                    previous_status,
+                    Some(ItemStatus::Failed) | Some(ItemStatus::Started)
+                );
+
+            // only the data types without existing output files are computed
+            let file_path_map = get_missing_outputs(
+                output_dir,
+                &opts.layout,
+                &rib_types,
+                options,
                 &item.collector_id,
-                ts.year(),
-                ts.month(),
-                ts.day(),
-                &timestamp
+                &ts,
+                opts.force || retry,
             );
-            if !opts.force && std::path::Path::new(output_path.as_str()).exists() {
+            if file_path_map.is_empty() {
                 info!(
-                    "result file {} already exists, skip processing",
-                    output_path
+                    "result files of {} already exist, skip processing",
+                    item.url.as_str()
                 );
+                if previous_status.is_none() {
+                    let record =
+                        manifest_record(item, ItemStatus::Skipped, started_at, None, vec![]);
+                    if let Err(e) = manifest.append(&record) {
+                        error!("failed to update run manifest: {}", e);
+                    }
+                }
+                record_status(item, ItemStatus::Skipped, Duration::ZERO, 0, 0);
                 let _ = s1.send(format!("{}-{}", item.collector_id.as_str(), timestamp));
                 return;
             }
-            file_path_map.insert(data_type.to_string(), output_path);
-        }
+            let rib_options = get_rib_options(opts, file_path_map.keys().copied().collect());
 
-        let project = match item.collector_id.starts_with("rrc") {
-            true => "riperis".to_string(),
-            false => "route-views".to_string(),
-        };
+            let record = manifest_record(item, ItemStatus::Started, started_at, None, vec![]);
+            if let Err(e) = manifest.append(&record) {
+                error!("failed to update run manifest: {}", e);
+            }
 
-        // parsing and writing out info, manually scoping to potentially avoid memory issue
-        {
-            info!("start parsing file {}", item.url.as_str());
-            let (peer_stats, pfx2as, (as2rel_global, as2rel_v4, as2rel_v6)) = match parse_rib_file(
+            info!(
+                "start parsing file {} for {}",
                 item.url.as_str(),
-                project.as_str(),
-                item.collector_id.as_str(),
-            ) {
-                Ok(i) => i,
-                Err(_) => {
-                    error!("processing of file {} failed", item.url.as_str());
+                rib_options
+                    .types
+                    .iter()
+                    .map(|t| t.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+            // held until all attempts have exited, as timed-out attempts are waited for
+            let permit = budget.as_ref().map(|b| b.acquire(estimate_memory(item)));
+            let item_start = Instant::now();
+            let bytes_read = item.rough_size.max(0) as u64;
+            let task_item = item.clone();
+            let result = retry_policy
+                .run(item.url.as_str(), move |cancellation| {
+                    parse_rib_file_types(
+                        task_item.url.as_str(),
+                        task_item.project.as_str(),
+                        task_item.collector_id.as_str(),
+                        &rib_options,
+                        cancellation,
+                    )
+                })
+                .and_then(|results| {
+                    write_rib_results(&file_path_map, &results, &ts, options, opts.verify)
+                        .map(|_| results.elems_count)
+                        .map_err(|e| (FailureKind::Write, e))
+                });
+            drop(permit);
+            let elems_count = match result {
+                Ok(count) => count,
+                Err((kind, e)) => {
+                    error!(
+                        "processing of file {} failed ({}): {:#}",
+                        item.url.as_str(),
+                        kind,
+                        e
+                    );
+                    let record = manifest_record(
+                        item,
+                        ItemStatus::Failed,
+                        started_at,
+                        Some(format!("{}: {:#}", kind, e)),
+                        vec![],
+                    );
+                    if let Err(e) = manifest.append(&record) {
+                        error!("failed to update run manifest: {}", e);
+                    }
+                    record_status(
+                        item,
+                        ItemStatus::Failed,
+                        item_start.elapsed(),
+                        bytes_read,
+                        0,
+                    );
                     let _ = s1.send(format!("{}-{}", item.collector_id.as_str(), timestamp));
                     return;
                 }
             };
 
-            write_results(
-                file_path_map.get("peer-stats").unwrap().as_str(),
-                &json!(peer_stats),
-            );
-            write_results(
-                file_path_map.get("pfx2as").unwrap().as_str(),
-                &json!(pfx2as),
-            );
-            write_results(
-                file_path_map.get("as2rel").unwrap().as_str(),
-                &json!(as2rel_global),
-            );
-            write_results(
-                file_path_map.get("as2rel-v4").unwrap().as_str(),
-                &json!(as2rel_v4),
-            );
-            write_results(
-                file_path_map.get("as2rel-v6").unwrap().as_str(),
-                &json!(as2rel_v6),
+            let outputs = file_path_map
+                .values()
+                .filter_map(|path| {
+                    Some(ManifestOutput {
+                        path: path.clone(),
+                        sha256: file_sha256(path.as_str()).ok()?,
+                    })
+                })
+                .collect();
+            let record = manifest_record(item, ItemStatus::Succeeded, started_at, None, outputs);
+            if let Err(e) = manifest.append(&record) {
+                error!("failed to update run manifest: {}", e);
+            }
+            record_status(
+                item,
+                ItemStatus::Succeeded,
+                item_start.elapsed(),
+                bytes_read,
+                elems_count,
             );
-        }
 
-        let _ = s1.send(format!("{}-{}", item.collector_id.as_str(), timestamp));
+            let _ = s1.send(format!("{}-{}", item.collector_id.as_str(), timestamp));
 
-        info!("processing file {} finished", item.url.as_str());
-    }); // end of parallel iter
+            info!("processing file {} finished", item.url.as_str());
+        }); // end of parallel iter
 
     handle.join().unwrap();
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.duration_secs = run_start.elapsed().as_secs_f64();
+    (summary, statuses.into_inner().unwrap())
+}
+
+/// Print the summary of processed RIB dumps and write it as Prometheus metrics if configured.
+fn report_summary(opts: &Opts, summary: &RunSummary) {
+    println!("{}", serde_json::to_string(summary).unwrap());
+    if let Some(path) = &opts.prometheus_file {
+        if let Err(e) = summary.write_prometheus(path, Utc::now().timestamp()) {
+            error!("failed to write metrics to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Run the index commands for a day with new data files.
+fn run_index_commands(opts: &Opts, date: &NaiveDate) {
+    for template in &opts.index_command {
+        let command = template
+            .replace("{date}", date.format("%Y-%m-%d").to_string().as_str())
+            .replace("{output_dir}", opts.output_dir().to_str().unwrap());
+        info!("running index command {}", command);
+        match Command::new("sh").arg("-c").arg(command.as_str()).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => error!("index command {} failed with {}", command, status),
+            Err(e) => error!("couldn't run index command {}: {}", command, e),
+        }
+    }
+}
+
+/// Poll for new RIB dumps until stopped, processing each dump once and running the index commands
+/// for the days of the new dumps.
+///
+/// Every poll covers whole days from the lookback period until now, so that dumps appearing late
+/// are still processed and selection policies see all dumps of a day. Policies targeting times of
+/// day only select from days that are over. Failed dumps are tried again in the following polls,
+/// up to [WATCH_MAX_ATTEMPTS] times.
+fn watch(opts: &Opts, options: &OutputOptions) {
+    let output_dir = opts.output_dir();
+    fs::create_dir_all(output_dir).unwrap();
+    let cursor_path = output_dir.join(WATCH_CURSOR_FILE);
+    let policy = get_selection_policy(opts);
+    let window_start = || {
+        let now = Utc::now().naive_utc();
+        let mut start = now - chrono::Duration::hours(opts.watch_lookback);
+        // the previous day is always covered when only complete days are selected from
+        if policy.needs_complete_days() {
+            start = start.min(now - chrono::Duration::days(1));
+        }
+        start
+            .date()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp()
+    };
+    let mut cursor = WatchCursor::load(&cursor_path)
+        .unwrap()
+        .unwrap_or_else(|| WatchCursor::new(window_start()));
+    let filter = get_collector_filter(opts);
+    info!(
+        "watching for new RIB dumps since {}, cursor at {}",
+        cursor.since,
+        cursor_path.display()
+    );
+
+    loop {
+        let since = DateTime::from_timestamp(cursor.since, 0)
+            .unwrap()
+            .naive_utc();
+        let now = Utc::now().naive_utc();
+        match query_dump_items(opts, Some("rib"), &since, &now, &filter) {
+            Ok(items) => {
+                // dumps closest to target times are only chosen once all dumps of the day can be
+                // seen, so that a dump arriving later never adds a second dump for a target
+                let items: Vec<DumpItem> = items
+                    .into_iter()
+                    .filter(|item| {
+                        !policy.needs_complete_days() || item.ts_start.date() < now.date()
+                    })
+                    .collect();
+                let items: Vec<DumpItem> = select_rib_items(opts, items)
+                    .into_iter()
+                    .filter(|item| !cursor.is_processed(item))
+                    .collect();
+                if !items.is_empty() {
+                    info!("processing {} new RIB dumps", items.len());
+                    let (summary, statuses) = process_ribs(opts, options, items.clone());
+                    report_summary(opts, &summary);
+
+                    let mut dates = BTreeSet::new();
+                    for item in &items {
+                        match statuses.get(&item.url) {
+                            Some(ItemStatus::Succeeded) => {
+                                dates.insert(item.ts_start.date());
+                                cursor.mark_processed(item);
+                            }
+                            Some(ItemStatus::Skipped) => cursor.mark_processed(item),
+                            _ => {
+                                if cursor.mark_failed(item, WATCH_MAX_ATTEMPTS) {
+                                    error!(
+                                        "giving up on {} after {} failed polls",
+                                        item.url, WATCH_MAX_ATTEMPTS
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    for date in &dates {
+                        run_index_commands(opts, date);
+                    }
+                }
+                cursor.advance(window_start());
+                if let Err(e) = cursor.save(&cursor_path) {
+                    error!("{:#}", e);
+                }
+            }
+            Err(e) => error!("querying new RIB dumps failed: {:#}", e),
+        }
+        thread::sleep(Duration::from_secs(opts.poll_interval * 60));
+    }
+}
+
+fn main() {
+    let matches = Opts::command().get_matches();
+    let mut opts = Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(path) = &opts.config {
+        let config = BootstrapConfig::from_file(path).unwrap_or_else(|e| {
+            Opts::command()
+                .error(ErrorKind::InvalidValue, format!("{:#}", e))
+                .exit()
+        });
+        apply_config(&mut opts, &matches, config);
+    }
+    if opts.output_dir.is_none() {
+        Opts::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no output directory given with --output-dir or in the config file",
+            )
+            .exit();
+    }
+    if !opts.report
+        && !opts.watch
+        && opts.reconstruct_at.is_none()
+        && opts.backfill_months.is_none()
+        && opts.last.is_none()
+        && opts.ts_start.is_none()
+    {
+        Opts::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no time range given with --ts-start, --last, --backfill-months or in the config file",
+            )
+            .exit();
+    }
+
+    if let Some(path) = &opts.project_mapping {
+        opts.mapping = ProjectMapping::from_file(path).unwrap_or_else(|e| {
+            Opts::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("couldn't load project mapping {}: {:#}", path.display(), e),
+                )
+                .exit()
+        });
+    }
+
+    if opts.debug {
+        tracing_subscriber::fmt()
+            // filter spans/events with level TRACE or higher.
+            .with_max_level(Level::INFO)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
+    let mut num_threads = num_cpus::get();
+    if let Some(t) = opts.max_threads {
+        if t <= num_threads {
+            // ensure the number of threads we use does not exceed the maximum threads count
+            num_threads = t;
+        }
+    }
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+        .unwrap();
+
+    info!("using maximum {} threads for processing.", num_threads);
+
+    if opts.report {
+        let manifest_path = opts.output_dir().join(MANIFEST_FILE);
+        let latest = RunManifest::load_latest(&manifest_path).unwrap();
+        let report = ManifestReport::from_records(&latest);
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    let output_options = OutputOptions::new(opts.format, opts.compression, opts.compression_level)
+        .unwrap_or_else(|e| {
+            Opts::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("invalid output options: {:#}", e),
+                )
+                .exit()
+        });
+
+    if opts.watch {
+        watch(&opts, &output_options);
+        return;
+    }
+
+    let summary = match opts.reconstruct_at {
+        Some(target_ts) => process_reconstruct(&opts, &output_options, target_ts),
+        None => process_ranges(&opts, &output_options),
+    };
+    let Some(summary) = summary else {
+        return;
+    };
+
+    report_summary(&opts, &summary);
+    if summary.failed_queries > 0 {
+        error!(
+            "querying dump files failed for {} time ranges",
+            summary.failed_queries
+        );
+        std::process::exit(1);
+    }
+    if summary.failure_ratio() > opts.max_failure_ratio {
+        error!(
+            "{} of {} processed dump files failed, above the maximum failure ratio {}",
+            summary.failed,
+            summary.succeeded + summary.failed,
+            opts.max_failure_ratio
+        );
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_results_atomic() {
+        let dir = std::env::temp_dir().join(format!("write-results-test-{}", std::process::id()));
+        let output_path = dir.join("pfx2as/rrc00/2022/02/pfx2as_rrc00_2022-02-01_1643673600.bz2");
+        let output_path = output_path.to_str().unwrap();
+
+        let options = OutputOptions::default();
+        write_results(output_path, &json!({"pfx2as": [1, 2, 3]}), &options, true).unwrap();
+        assert!(std::path::Path::new(output_path).exists());
+        assert!(!std::path::Path::new(format!("{}.tmp", output_path).as_str()).exists());
+        verify_results(output_path, &options).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_rib_artifact() {
+        let dir = std::env::temp_dir().join(format!("find-artifact-test-{}", std::process::id()));
+        let output_dir = dir.to_str().unwrap();
+        let layout: PathLayout = "hive".parse().unwrap();
+        let ts = |t: i64| DateTime::from_timestamp(t, 0).unwrap().naive_utc();
+        for (collector, t, ext) in [
+            ("route-views_sg", 1643673600, "bz2"),
+            ("route-views_sg", 1643702400, "parquet"),
+            ("route-views_sg", 1643731200, "ndjson.zst"),
+            ("route-views", 1643702400, "bz2"),
+        ] {
+            let path = layout.format(output_dir, "pfx2as", collector, &ts(t), ext);
+            fs::create_dir_all(std::path::Path::new(path.as_str()).parent().unwrap()).unwrap();
+            File::create(path).unwrap();
+        }
+
+        let found = find_rib_artifact(
+            output_dir,
+            &layout,
+            "pfx2as",
+            "route-views_sg",
+            &ts(1643710000),
+        )
+        .unwrap();
+        assert!(found.ends_with("pfx2as_route-views_sg_2022-02-01_1643702400.parquet"));
+        assert!(find_rib_artifact(
+            output_dir,
+            &layout,
+            "as2rel",
+            "route-views_sg",
+            &ts(1643710000)
+        )
+        .is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let config: BootstrapConfig = r#"
+            [collectors]
+            include = ["rrc*"]
+            [time]
+            start = "2022-01-01"
+            end = "2022-02-01"
+            select = "first-per-day"
+            [output]
+            dir = "/data/peer-stats"
+            format = "parquet"
+            [limits]
+            retries = 5
+            max_failure_ratio = 0.1
+            [tier1]
+            true_tier1 = [174]
+        "#
+        .parse()
+        .unwrap();
+        let matches = Opts::command().get_matches_from([
+            "peer-stats-bootstrap",
+            "--config",
+            "bootstrap.toml",
+            "--format",
+            "ndjson",
+            "--backfill-months",
+            "3",
+        ]);
+        let mut opts = Opts::from_arg_matches(&matches).unwrap();
+        apply_config(&mut opts, &matches, config);
+
+        // flags take precedence, the time range of the config is replaced as a whole
+        assert_eq!(opts.format, OutputFormat::Ndjson);
+        assert_eq!(opts.backfill_months, Some(3));
+        assert_eq!(opts.ts_start, None);
+        assert_eq!(opts.output_dir(), Path::new("/data/peer-stats"));
+        assert_eq!(opts.collector, vec!["rrc*"]);
+        assert_eq!(opts.select, SelectionPolicy::FirstPerDay);
+        assert_eq!(opts.retries, 5);
+        assert_eq!(opts.max_failure_ratio, 0.1);
+        assert_eq!(opts.tier1.true_tier1, vec![174]);
+    }
+
+    #[test]
+    fn test_retry_policy_limits() {
+        let opts = Opts::parse_from([
+            "peer-stats-bootstrap",
+            "--config",
+            "bootstrap.toml",
+            "--retries",
+            "4294967295",
+            "--timeout",
+            "18446744073709551615",
+        ]);
+        let policy = get_retry_policy(&opts);
+        assert_eq!(policy.max_attempts, u32::MAX);
+        assert_eq!(policy.timeout, Some(Duration::from_secs(u64::MAX)));
+    }
+
+    #[test]
+    fn test_parse_failure_ratio() {
+        assert_eq!(parse_failure_ratio("0"), Ok(0.0));
+        assert_eq!(parse_failure_ratio("1"), Ok(1.0));
+        assert_eq!(parse_failure_ratio("0.25"), Ok(0.25));
+        assert!(parse_failure_ratio("-0.1").is_err());
+        assert!(parse_failure_ratio("1.5").is_err());
+        assert!(parse_failure_ratio("NaN").is_err());
+        assert!(parse_failure_ratio("half").is_err());
+    }
 }
@@ -1,9 +1,12 @@
+use anyhow::{Context, Result};
 use chrono::{NaiveDate, Utc};
 use clap::Parser;
-use peer_stats::{As2Rel, As2RelCount};
-use serde_json::json;
-use std::collections::HashMap;
-use std::io::Read;
+use peer_stats::codec::{Codec, CompressedWriter};
+use peer_stats::format::{for_each_row, write_json, DataSource};
+use peer_stats::{As2Rel, As2RelCount, PathLayout};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 use std::path::PathBuf;
 use tracing::info;
 use walkdir::WalkDir;
@@ -23,21 +26,136 @@ struct Opts {
 
     #[clap(long)]
     allow_previous_day: bool,
+
+    /// date of the data files to aggregate, `YYYY-MM-DD`, today by default
+    #[clap(long)]
+    date: Option<NaiveDate>,
+
+    /// layout of the data file paths: `default`, `hive` or a custom template, see
+    /// `peer-stats-bootstrap --layout`
+    #[clap(long, default_value = "default")]
+    layout: PathLayout,
+
+    /// Keep per-collector contributions as a `collectors` array on each aggregated record
+    #[clap(long)]
+    per_collector: bool,
+
+    /// compression of the output files: bz2, gz, zst or none
+    #[clap(long, default_value = "bz2")]
+    compression: Codec,
+
+    /// compression level, 9 for bz2, 6 for gz and 3 for zst by default
+    #[clap(long)]
+    compression_level: Option<u32>,
 }
 
-fn get_ymd_from_file(file_path: &str) -> (i32, u32, u32) {
-    let date_part = file_path.split('_').collect::<Vec<&str>>();
-    let parts = date_part[date_part.len() - 2]
-        .split('-')
-        .collect::<Vec<&str>>();
-    (
-        parts[0].parse::<i32>().unwrap(),
-        parts[1].parse::<u32>().unwrap(),
-        parts[2].parse::<u32>().unwrap(),
-    )
+/// (project, collector) -> (paths_count, peers_count)
+type CollectorCountsMap = BTreeMap<(String, String), (usize, usize)>;
+
+/// Contribution of a single collector to an aggregated as2rel record.
+#[derive(Debug, Clone, Serialize)]
+struct CollectorCount {
+    project: String,
+    collector: String,
+    paths_count: usize,
+    peers_count: usize,
+}
+
+/// Aggregated as2rel record with per-collector contributions.
+#[derive(Debug, Clone, Serialize)]
+struct As2RelCollectorsCount {
+    asn1: u32,
+    asn2: u32,
+    rel: u8,
+    paths_count: usize,
+    peers_count: usize,
+    collectors: Vec<CollectorCount>,
 }
 
-fn main() {
+/// Sums the as2rel counts of all data files, and of each collector with `--per-collector`.
+struct As2RelAggregator {
+    per_collector: bool,
+    data_map: HashMap<(u32, u32, u8), (usize, usize)>,
+    /// (asn1, asn2, rel) -> (project, collector) -> counts, only populated with `--per-collector`
+    collectors_map: HashMap<(u32, u32, u8), CollectorCountsMap>,
+}
+
+impl As2RelAggregator {
+    fn new(per_collector: bool) -> Self {
+        As2RelAggregator {
+            per_collector,
+            data_map: HashMap::new(),
+            collectors_map: HashMap::new(),
+        }
+    }
+
+    fn process_entry(&mut self, source: &DataSource, as2rel: &As2RelCount) {
+        let key = (as2rel.asn1, as2rel.asn2, as2rel.rel);
+        if self.per_collector {
+            let (count_1, count_2) = self
+                .collectors_map
+                .entry(key)
+                .or_default()
+                .entry((source.project.clone(), source.collector.clone()))
+                .or_insert((0, 0));
+            *count_1 += as2rel.paths_count;
+            *count_2 += as2rel.peers_count;
+        }
+        let (count_1, count_2) = self.data_map.entry(key).or_insert((0, 0));
+        *count_1 += as2rel.paths_count;
+        *count_2 += as2rel.peers_count;
+    }
+
+    /// The aggregated records, and the records with per-collector contributions if enabled.
+    fn into_counts(mut self) -> (Vec<As2RelCount>, Vec<As2RelCollectorsCount>) {
+        let counts: Vec<As2RelCount> = self
+            .data_map
+            .into_iter()
+            .map(
+                |((asn1, asn2, rel), (paths_count, peers_count))| As2RelCount {
+                    asn1,
+                    asn2,
+                    rel,
+                    paths_count,
+                    peers_count,
+                },
+            )
+            .collect();
+        if !self.per_collector {
+            return (counts, vec![]);
+        }
+        let collectors_counts = counts
+            .iter()
+            .map(|count| {
+                let collectors = self
+                    .collectors_map
+                    .remove(&(count.asn1, count.asn2, count.rel))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(
+                        |((project, collector), (paths_count, peers_count))| CollectorCount {
+                            project,
+                            collector,
+                            paths_count,
+                            peers_count,
+                        },
+                    )
+                    .collect();
+                As2RelCollectorsCount {
+                    asn1: count.asn1,
+                    asn2: count.asn2,
+                    rel: count.rel,
+                    paths_count: count.paths_count,
+                    peers_count: count.peers_count,
+                    collectors,
+                }
+            })
+            .collect();
+        (counts, collectors_counts)
+    }
+}
+
+fn main() -> Result<()> {
     let opts = Opts::parse();
 
     if opts.debug {
@@ -46,85 +164,64 @@ fn main() {
             .init();
     }
 
-    for file_prefix in ["as2rel_", "as2rel-v4_", "as2rel-v6_"] {
-        let file_paths = WalkDir::new(opts.data_dir.to_str().unwrap())
-            .follow_links(true)
+    let ts = opts.date.unwrap_or_else(|| Utc::now().date_naive());
+    for data_type in ["as2rel", "as2rel-v4", "as2rel-v6"] {
+        let file_paths = opts
+            .layout
+            .parse_unique(
+                WalkDir::new(opts.data_dir.to_str().unwrap())
+                    .follow_links(true)
+                    .into_iter()
+                    .filter_map(|e| Some(e.ok()?.path().to_str()?.to_string())),
+            )
             .into_iter()
-            .filter_map(|e| match e.ok() {
-                Some(entry) => {
-                    let path: String = entry.path().to_str().unwrap().to_string();
-                    let path_str = path.as_str();
-                    if path_str.contains(file_prefix) && path_str.ends_with(".bz2") {
-                        let (year, month, day) = get_ymd_from_file(path.as_str());
-                        let file_date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
-                        let ts = Utc::now().date_naive();
-                        if file_date == ts {
-                            return Some(path);
-                        }
-                        if opts.allow_previous_day && file_date == ts.pred_opt().unwrap() {
-                            return Some(path);
-                        }
-                    }
-                    None
+            .filter_map(|(file, path)| {
+                let is_date = file.date == ts
+                    || (opts.allow_previous_day && file.date == ts.pred_opt().unwrap());
+                match file.data_type == data_type && is_date {
+                    true => Some(path),
+                    false => None,
                 }
-                None => None,
             })
             .collect::<Vec<String>>();
 
         if file_paths.is_empty() {
             info!(
                 "no matching current date {} file found, skipping",
-                file_prefix
+                data_type
             );
-            return;
+            return Ok(());
         }
 
-        let mut data_map: HashMap<(u32, u32, u8), (usize, usize)> = HashMap::new();
-
+        let mut aggregator = As2RelAggregator::new(opts.per_collector);
         for file in file_paths {
             info!("processing {}", file.as_str());
-            let mut data = "".to_string();
-            oneio::get_reader(file.as_str())
-                .unwrap()
-                .read_to_string(&mut data)
-                .unwrap();
-            let as2rel_info: As2Rel = serde_json::from_str(&data).unwrap();
-
-            for as2rel in as2rel_info.as2rel {
-                let (asn1, asn2, rel, paths_count, peers_count) = (
-                    as2rel.asn1,
-                    as2rel.asn2,
-                    as2rel.rel,
-                    as2rel.paths_count,
-                    as2rel.peers_count,
-                );
-                let (count_1, count_2) = data_map.entry((asn1, asn2, rel)).or_insert((0, 0));
-                *count_1 += paths_count;
-                *count_2 += peers_count;
-            }
+            for_each_row::<As2Rel, _>(file.as_str(), |source, as2rel| {
+                aggregator.process_entry(source, &as2rel);
+            })
+            .unwrap();
         }
-
-        let res: Vec<As2RelCount> = data_map
-            .into_iter()
-            .map(
-                |((asn1, asn2, rel), (paths_count, peers_count))| As2RelCount {
-                    asn1,
-                    asn2,
-                    rel,
-                    paths_count,
-                    peers_count,
-                },
-            )
-            .collect();
+        let (res, res_collectors) = aggregator.into_counts();
 
         let output_file = format!(
-            "{}/{}-latest.json.bz2",
+            "{}/{}-latest.json{}",
             opts.output_dir.to_str().unwrap(),
-            file_prefix.strip_suffix('_').unwrap()
+            data_type,
+            opts.compression
+                .extension()
+                .map(|ext| format!(".{}", ext))
+                .unwrap_or_default()
         );
-        let mut writer = oneio::get_writer(output_file.as_str()).unwrap();
-        let _ = writer.write_all(serde_json::to_string_pretty(&json!(res)).unwrap().as_ref());
+        let file = File::create(output_file.as_str())
+            .with_context(|| format!("couldn't create {}", output_file))?;
+        let writer = CompressedWriter::new(file, opts.compression, opts.compression_level)?;
+        match opts.per_collector {
+            true => write_json(&res_collectors, writer),
+            false => write_json(&res, writer),
+        }
+        .with_context(|| format!("couldn't write {}", output_file))?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -132,14 +229,41 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_get_file_date() {
-        assert_eq!(
-            get_ymd_from_file("as2rel_rrc16_2022-02-01_1643673600.bz2"),
-            (2022, 2, 1)
-        );
-        assert_eq!(
-            get_ymd_from_file("/aaa_bbb-ccc/as2rel_rrc16_2022-02-01_1643673600.bz2"),
-            (2022, 2, 1)
-        );
+    fn test_aggregate_per_collector() {
+        let source = |collector: &str| DataSource {
+            project: "riperis".to_string(),
+            collector: collector.to_string(),
+            rib_dump_url: String::new(),
+        };
+        let count = |asn2, paths_count, peers_count| As2RelCount {
+            asn1: 65001,
+            asn2,
+            rel: 1,
+            paths_count,
+            peers_count,
+        };
+
+        let mut aggregator = As2RelAggregator::new(true);
+        aggregator.process_entry(&source("rrc00"), &count(65002, 10, 2));
+        aggregator.process_entry(&source("rrc01"), &count(65002, 5, 1));
+        aggregator.process_entry(&source("rrc01"), &count(65003, 3, 1));
+        let (counts, collectors_counts) = aggregator.into_counts();
+        assert_eq!(counts.len(), 2);
+
+        let record = collectors_counts.iter().find(|c| c.asn2 == 65002).unwrap();
+        assert_eq!((record.paths_count, record.peers_count), (15, 3));
+        let collectors: Vec<(&str, usize, usize)> = record
+            .collectors
+            .iter()
+            .map(|c| (c.collector.as_str(), c.paths_count, c.peers_count))
+            .collect();
+        assert_eq!(collectors, vec![("rrc00", 10, 2), ("rrc01", 5, 1)]);
+        let record = collectors_counts.iter().find(|c| c.asn2 == 65003).unwrap();
+        assert_eq!(record.collectors.len(), 1);
+        assert_eq!(record.paths_count, 3);
+
+        let mut aggregator = As2RelAggregator::new(false);
+        aggregator.process_entry(&source("rrc00"), &count(65002, 10, 2));
+        assert!(aggregator.into_counts().1.is_empty());
     }
 }
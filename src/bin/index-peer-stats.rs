@@ -1,39 +1,52 @@
-use anyhow::Result;
-use bzip2::read::BzDecoder;
-use chrono::{Datelike, Utc};
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate, Utc};
 use clap::Parser;
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::net::IpAddr;
+use peer_stats::format::{for_each_row, DataSource};
+use peer_stats::timerange::parse_time;
+use peer_stats::{CollectorFilter, DataFilePath, PathLayout, PeerInfo, RibPeerInfo};
+use rusqlite::{Connection, OptionalExtension};
 use std::path::PathBuf;
-use tracing::info;
+use std::time::UNIX_EPOCH;
+use tracing::{error, info};
 use walkdir::WalkDir;
 
 pub struct PeerStatsDb {
     db: Connection,
 }
 
-fn get_date_from_url(url: &str) -> (String, String, String) {
-    let parts = url.split('.').collect::<Vec<&str>>();
-    let date_str = parts[parts.len() - 3];
-    let year = date_str.get(0..=3).unwrap().to_string();
-    let month = date_str.get(4..=5).unwrap().to_string();
-    let day = date_str.get(6..=7).unwrap().to_string();
-    (year, month, day)
+/// Size and modification time identifying the version of a data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStamp {
+    pub path: String,
+    pub size: u64,
+    pub modified: i64,
 }
 
-impl PeerStatsDb {
-    pub fn new(db_path: &Option<String>) -> PeerStatsDb {
-        let db = match db_path {
-            Some(p) => Connection::open(p.as_str()).unwrap(),
-            None => Connection::open_in_memory().unwrap(),
-        };
+impl FileStamp {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("couldn't read metadata of {}", path))?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        Ok(FileStamp {
+            path: path.to_string(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
 
-        db.execute(
-            r#"
+/// Peer rows written and existing rows kept when ingesting a data file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    pub written: usize,
+    pub kept: usize,
+}
+
+/// Schema migrations applied in order, the schema version being the number of applied migrations.
+/// Databases created before versioning have the tables of the first migration already.
+const MIGRATIONS: &[&str] = &[
+    // 1: peer stats by date, and ingested data files
+    r#"
         create table if not exists peer_stats (
         date TEXT ,
         collector TEXT,
@@ -44,22 +57,66 @@ impl PeerStatsDb {
         num_connected_asns INTEGER,
         PRIMARY KEY (date, collector, ip)
         );
-        "#,
-            [],
-        )
-        .unwrap();
-
-        db.execute(
-            r#"
         create index if not exists date_index on peer_stats (
         date DESC
         );
-        "#,
+        create table if not exists ingested_files (
+        path TEXT PRIMARY KEY,
+        size INTEGER,
+        modified INTEGER,
+        ingested_at INTEGER,
+        num_peers INTEGER
+        );
+    "#,
+    // 2: project, timestamp and URL of the RIB dump, NULL for rows ingested before
+    r#"
+        alter table peer_stats add column project TEXT;
+        alter table peer_stats add column rib_timestamp INTEGER;
+        alter table peer_stats add column rib_dump_url TEXT;
+    "#,
+];
+
+impl PeerStatsDb {
+    pub fn new(db_path: &Option<String>) -> PeerStatsDb {
+        let db = match db_path {
+            Some(p) => Connection::open(p.as_str()).unwrap(),
+            None => Connection::open_in_memory().unwrap(),
+        };
+        let mut db = PeerStatsDb { db };
+        db.migrate().unwrap();
+        db
+    }
+
+    /// Number of schema migrations applied to the database.
+    pub fn schema_version(&self) -> Result<usize> {
+        self.db.execute(
+            "create table if not exists schema_version (version INTEGER NOT NULL)",
             [],
-        )
-        .unwrap();
+        )?;
+        let version: Option<usize> =
+            self.db
+                .query_row("SELECT max(version) FROM schema_version", [], |row| {
+                    row.get(0)
+                })?;
+        Ok(version.unwrap_or(0))
+    }
 
-        PeerStatsDb { db }
+    /// Apply the pending schema migrations, each in its own transaction.
+    fn migrate(&mut self) -> Result<()> {
+        let version = self.schema_version()?;
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.db.transaction()?;
+            tx.execute_batch(migration)
+                .with_context(|| format!("schema migration {} failed", idx + 1))?;
+            tx.execute("DELETE FROM schema_version", [])?;
+            tx.execute(
+                "INSERT INTO schema_version (version) VALUES (?1)",
+                (idx + 1,),
+            )?;
+            tx.commit()?;
+            info!("migrated database schema to version {}", idx + 1);
+        }
+        Ok(())
     }
 
     pub fn is_db_empty(&self) -> bool {
@@ -70,46 +127,121 @@ impl PeerStatsDb {
         count == 0
     }
 
-    pub fn insert_rib_info(&self, rib_info: &RibPeerInfo) -> bool {
-        let (year, month, day) = get_date_from_url(rib_info.rib_dump_url.as_str());
-        let date = format!("{}-{}-{}", year, month, day);
-        for (ip, peer) in &rib_info.peers {
-            let res = self.db.execute( r#"
-        INSERT INTO peer_stats (date, collector, ip, asn, num_v4_pfxs, num_v6_pfxs, num_connected_asns)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-        "#, (
-                date.as_str(),
-                rib_info.collector.as_str(),
-                ip.to_string().as_str(),
-                peer.asn,
-                peer.num_v4_pfxs,
-                peer.num_v6_pfxs,
-                peer.num_connected_asns,
+    /// The version of a data file recorded when it was last ingested.
+    pub fn get_ingested(&self, path: &str) -> Result<Option<FileStamp>> {
+        Ok(self
+            .db
+            .query_row(
+                "SELECT size, modified FROM ingested_files WHERE path = ?1",
+                (path,),
+                |row| {
+                    Ok(FileStamp {
+                        path: path.to_string(),
+                        size: row.get(0)?,
+                        modified: row.get(1)?,
+                    })
+                },
             )
-            );
-            if res.is_err() {
-                return false;
-            }
-        }
-        true
+            .optional()?)
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RibPeerInfo {
-    project: String,
-    collector: String,
-    rib_dump_url: String,
-    peers: HashMap<IpAddr, PeerInfo>,
-}
+    /// Insert the peers of a data file and record the file as ingested, in a single transaction.
+    /// Date and RIB dump timestamp are taken from the data file path. NDJSON files are read row
+    /// by row.
+    ///
+    /// Peers already stored for the same date, collector and IP are kept, or updated with
+    /// `replace`. Any other error rolls back the whole file.
+    pub fn insert_data_file(
+        &mut self,
+        data_file: &DataFilePath,
+        file: &FileStamp,
+        replace: bool,
+    ) -> Result<IngestStats> {
+        self.insert_peers(data_file, file, replace, |insert| {
+            for_each_row::<RibPeerInfo, _>(file.path.as_str(), insert)
+        })
+    }
+
+    /// Insert the peers `read` passes to its callback, see [PeerStatsDb::insert_data_file].
+    fn insert_peers<F>(
+        &mut self,
+        data_file: &DataFilePath,
+        file: &FileStamp,
+        replace: bool,
+        read: F,
+    ) -> Result<IngestStats>
+    where
+        F: FnOnce(&mut dyn FnMut(&DataSource, PeerInfo)) -> Result<()>,
+    {
+        let date = data_file.date.format("%Y-%m-%d").to_string();
+        let on_conflict = match replace {
+            true => {
+                "DO UPDATE SET asn = excluded.asn, num_v4_pfxs = excluded.num_v4_pfxs, \
+                 num_v6_pfxs = excluded.num_v6_pfxs, num_connected_asns = excluded.num_connected_asns, \
+                 project = excluded.project, rib_timestamp = excluded.rib_timestamp, \
+                 rib_dump_url = excluded.rib_dump_url"
+            }
+            false => "DO NOTHING",
+        };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PeerInfo {
-    ip: IpAddr,
-    asn: u32,
-    num_v4_pfxs: usize,
-    num_v6_pfxs: usize,
-    num_connected_asns: usize,
+        let mut stats = IngestStats::default();
+        let mut num_peers = 0;
+        let tx = self.db.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                format!(
+                    r#"
+        INSERT INTO peer_stats (date, collector, ip, asn, num_v4_pfxs, num_v6_pfxs, num_connected_asns, project, rib_timestamp, rib_dump_url)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        ON CONFLICT (date, collector, ip) {}
+        "#,
+                    on_conflict
+                )
+                .as_str(),
+            )?;
+            // the first failing row fails the file, the remaining rows are skipped
+            let mut inserted = Ok(());
+            read(&mut |source, peer| {
+                if inserted.is_err() {
+                    return;
+                }
+                num_peers += 1;
+                inserted = stmt
+                    .execute((
+                        date.as_str(),
+                        source.collector.as_str(),
+                        peer.ip.to_string().as_str(),
+                        peer.asn,
+                        peer.num_v4_pfxs,
+                        peer.num_v6_pfxs,
+                        peer.num_connected_asns,
+                        source.project.as_str(),
+                        data_file.ts,
+                        source.rib_dump_url.as_str(),
+                    ))
+                    .map(|changed| match changed {
+                        0 => stats.kept += 1,
+                        _ => stats.written += 1,
+                    });
+            })?;
+            inserted?;
+        }
+        tx.execute(
+            r#"
+        INSERT OR REPLACE INTO ingested_files (path, size, modified, ingested_at, num_peers)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        "#,
+            (
+                file.path.as_str(),
+                file.size,
+                file.modified,
+                Utc::now().timestamp(),
+                num_peers,
+            ),
+        )?;
+        tx.commit()?;
+        Ok(stats)
+    }
 }
 
 /// peer-stats is a CLI tool that collects peer information from a given RIB dump file.
@@ -122,110 +254,401 @@ struct Opts {
     data_dir: PathBuf,
 
     /// Whether to bootstrap the whole database, otherwise, only process the latest
-    #[clap(long, short)]
+    #[clap(long, short, conflicts_with_all = ["from", "to"])]
     bootstrap: bool,
 
+    /// first date of the data files to index, as `YYYY-MM-DD` or relative time such as `-7d`
+    #[clap(long, value_parser = parse_date, allow_hyphen_values = true)]
+    from: Option<NaiveDate>,
+
+    /// last date of the data files to index, in the same forms as `--from`
+    #[clap(long, value_parser = parse_date, allow_hyphen_values = true)]
+    to: Option<NaiveDate>,
+
+    /// collectors to index, comma-separated or repeated, with `*` and `?` wildcards; all by default
+    #[clap(long, value_delimiter = ',')]
+    collector: Vec<String>,
+
+    /// collectors to skip, comma-separated or repeated, with `*` and `?` wildcards
+    #[clap(long, value_delimiter = ',')]
+    exclude_collector: Vec<String>,
+
+    /// replace peers already stored for the same date, collector and IP, and ingest files again
+    /// even if they were ingested before; existing peers are kept otherwise
+    #[clap(long)]
+    replace: bool,
+
     /// whether to print debug
     #[clap(long)]
     debug: bool,
+
+    /// layout of the data file paths: `default`, `hive` or a custom template, see
+    /// `peer-stats-bootstrap --layout`
+    #[clap(long, default_value = "default")]
+    layout: PathLayout,
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    parse_time(s, Utc::now().naive_utc()).map(|t| t.date())
+}
+
+/// Whether data files of a date are indexed: those within `--from` and `--to` if either is given,
+/// all with `--bootstrap`, and otherwise the latest, today and also yesterday on the first day of
+/// a month.
+fn is_selected_date(opts: &Opts, date: NaiveDate, today: NaiveDate) -> bool {
+    if opts.from.is_some() || opts.to.is_some() {
+        return !matches!(opts.from, Some(from) if date < from)
+            && !matches!(opts.to, Some(to) if date > to);
+    }
+    if opts.bootstrap {
+        return true;
+    }
+    let yesterday = today.pred_opt().unwrap();
+    date == today || (date == yesterday && yesterday.month() != today.month())
 }
 
-fn get_ymd_from_file(file_path: &str) -> Result<(i32, u32, u32)> {
-    let date_part = file_path.split('_').collect::<Vec<&str>>();
-    let parts = date_part[date_part.len() - 2]
-        .split('-')
-        .collect::<Vec<&str>>();
-    Ok((
-        parts[0].parse::<i32>()?,
-        parts[1].parse::<u32>()?,
-        parts[2].parse::<u32>()?,
-    ))
+/// A data file to ingest.
+struct PendingFile {
+    data_file: DataFilePath,
+    stamp: FileStamp,
+    replace: bool,
 }
 
 fn main() {
     let opts = Opts::parse();
 
-    if opts.debug {
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::INFO)
-            .init();
+    // errors are always reported, progress only with --debug
+    tracing_subscriber::fmt()
+        .with_max_level(match opts.debug {
+            true => tracing::Level::INFO,
+            false => tracing::Level::ERROR,
+        })
+        .init();
+
+    if let (Some(from), Some(to)) = (opts.from, opts.to) {
+        if to < from {
+            error!("--to {} is before --from {}", to, from);
+            std::process::exit(2);
+        }
     }
 
-    let db = PeerStatsDb::new(&Some(opts.db_file.to_str().unwrap().to_string()));
+    let mut db = PeerStatsDb::new(&Some(opts.db_file.to_str().unwrap().to_string()));
+    let collector_filter = CollectorFilter::new(
+        opts.collector.clone(),
+        opts.exclude_collector.clone(),
+        vec![],
+    );
+    let today = Utc::now().date_naive();
 
-    let file_paths = WalkDir::new(opts.data_dir.to_str().unwrap())
-        .follow_links(true)
+    let file_paths = opts
+        .layout
+        .parse_unique(
+            WalkDir::new(opts.data_dir.to_str().unwrap())
+                .follow_links(true)
+                .into_iter()
+                .filter_map(|e| Some(e.ok()?.path().to_str()?.to_string())),
+        )
         .into_iter()
-        .filter_map(|e| match e.ok() {
-            Some(entry) => {
-                let path: String = entry.path().to_str().unwrap().to_string();
-                let path_str = path.as_str();
-                if path_str.contains("peer-stats_") && path_str.ends_with(".bz2") {
-                    return if opts.bootstrap {
-                        Some(path)
-                    } else {
-                        let (year, month, day) = match get_ymd_from_file(path.as_str()) {
-                            Ok(x) => x,
-                            Err(_) => return None,
-                        };
-                        let ts = Utc::now();
-                        let ts2 = ts - chrono::Duration::days(1);
-
-                        let expected_dates = match ts.month() == ts2.month() {
-                            true => {
-                                vec![(ts.year(), ts.month(), ts.day())]
-                            }
-                            false => {
-                                vec![
-                                    (ts.year(), ts.month(), ts.day()),
-                                    (ts2.year(), ts2.month(), ts2.day()),
-                                ]
-                            }
-                        };
-
-                        if expected_dates
-                            .into_iter()
-                            .any(|(y, m, d)| y == year && m == month && d == day)
-                        {
-                            Some(path)
-                        } else {
-                            None
-                        }
-                    };
-                }
-                None
+        .filter_map(|(file, path)| {
+            match file.data_type == "peer-stats"
+                && collector_filter.matches_collector(file.collector.as_str())
+                && is_selected_date(&opts, file.date, today)
+            {
+                true => Some(path),
+                false => None,
             }
-            None => None,
         })
         .collect::<Vec<String>>();
 
-    for file in file_paths {
-        info!("processing {}", file.as_str());
-        let mut reader = BufReader::new(BzDecoder::new(File::open(file.as_str()).unwrap()));
-        let mut data = "".to_string();
-        reader.read_to_string(&mut data).unwrap();
-        let rib_info: RibPeerInfo = serde_json::from_str(&data).unwrap();
-        if !db.insert_rib_info(&rib_info) {
-            info!("data already exists, skipping: {}", file.as_str());
-        } else {
-            info!("processing {} finished ", file.as_str());
+    let mut failed = 0;
+    let mut pending = vec![];
+    for file in &file_paths {
+        match prepare_file(&db, &opts.layout, file.as_str(), opts.replace) {
+            Ok(Some(p)) => pending.push(p),
+            Ok(None) => info!("already ingested, skipping: {}", file),
+            Err(e) => {
+                error!("failed to ingest {}: {:#}", file, e);
+                failed += 1;
+            }
+        }
+    }
+    info!(
+        "{} of {} data files to ingest",
+        pending.len(),
+        file_paths.len()
+    );
+
+    // data files are written in order of date, collector and dump timestamp, so that without
+    // --replace the first dump of a day provides the peers
+    pending.sort_by(|a, b| {
+        (a.data_file.date, &a.data_file.collector, a.data_file.ts).cmp(&(
+            b.data_file.date,
+            &b.data_file.collector,
+            b.data_file.ts,
+        ))
+    });
+    for file in pending {
+        let path = file.stamp.path.as_str();
+        match db.insert_data_file(&file.data_file, &file.stamp, file.replace) {
+            Ok(stats) => info!(
+                "processing {} finished, {} peers written, {} existing peers kept",
+                path, stats.written, stats.kept
+            ),
+            Err(e) => {
+                error!("failed to ingest {}: {:#}", path, e);
+                failed += 1;
+            }
         }
     }
+
+    if failed > 0 {
+        error!("{} of {} data files failed", failed, file_paths.len());
+        std::process::exit(1);
+    }
+}
+
+/// Check whether a data file needs to be ingested, `None` if it was ingested before in the same
+/// version. Files that changed since they were ingested replace their peers.
+fn prepare_file(
+    db: &PeerStatsDb,
+    layout: &PathLayout,
+    file: &str,
+    replace: bool,
+) -> Result<Option<PendingFile>> {
+    let data_file = layout
+        .parse(file)
+        .with_context(|| format!("{} does not match the layout {}", file, layout.template()))?;
+    let stamp = FileStamp::from_path(file)?;
+    let previous = db.get_ingested(file)?;
+    if !replace && previous.as_ref() == Some(&stamp) {
+        return Ok(None);
+    }
+    Ok(Some(PendingFile {
+        data_file,
+        stamp,
+        replace: replace || previous.is_some(),
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use peer_stats::format::write_ndjson;
+    use std::collections::HashMap;
+
+    fn rib_info(asn: u32, num_v4_pfxs: usize) -> RibPeerInfo {
+        let ip = "192.0.2.1".parse().unwrap();
+        RibPeerInfo {
+            project: "riperis".to_string(),
+            collector: "rrc00".to_string(),
+            rib_dump_url: "https://data.ris.ripe.net/rrc00/2022.02/bview.20220201.0000.gz"
+                .to_string(),
+            peers: HashMap::from([(
+                ip,
+                PeerInfo {
+                    ip,
+                    asn,
+                    num_v4_pfxs,
+                    num_v6_pfxs: 0,
+                    num_connected_asns: 1,
+                },
+            )]),
+        }
+    }
+
+    /// Insert the peers of a `RibPeerInfo` as if they were read from a data file.
+    fn insert_rib_info(
+        db: &mut PeerStatsDb,
+        rib_info: &RibPeerInfo,
+        data_file: &DataFilePath,
+        file: &FileStamp,
+        replace: bool,
+    ) -> Result<IngestStats> {
+        let source = DataSource {
+            project: rib_info.project.clone(),
+            collector: rib_info.collector.clone(),
+            rib_dump_url: rib_info.rib_dump_url.clone(),
+        };
+        db.insert_peers(data_file, file, replace, |insert| {
+            for peer in rib_info.peers.values() {
+                insert(&source, peer.clone());
+            }
+            Ok(())
+        })
+    }
 
     #[test]
-    fn test_get_file_date() {
+    fn test_insert_rib_info() {
+        let mut db = PeerStatsDb::new(&None);
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        let stamp = FileStamp {
+            path: "peer-stats/rrc00/2022/02/peer-stats_rrc00_2022-02-01_1643673600.bz2".to_string(),
+            size: 100,
+            modified: 1643673700,
+        };
+        let data_file = PathLayout::new(PathLayout::DEFAULT)
+            .unwrap()
+            .parse(stamp.path.as_str())
+            .unwrap();
+        assert!(db.get_ingested(stamp.path.as_str()).unwrap().is_none());
+
+        let stats =
+            insert_rib_info(&mut db, &rib_info(64496, 10), &data_file, &stamp, false).unwrap();
+        assert_eq!(
+            stats,
+            IngestStats {
+                written: 1,
+                kept: 0
+            }
+        );
+        assert_eq!(
+            db.get_ingested(stamp.path.as_str()).unwrap(),
+            Some(stamp.clone())
+        );
+
+        // conflicting peers are kept unless replaced
+        let stats =
+            insert_rib_info(&mut db, &rib_info(64496, 20), &data_file, &stamp, false).unwrap();
+        assert_eq!(
+            stats,
+            IngestStats {
+                written: 0,
+                kept: 1
+            }
+        );
+        let stats =
+            insert_rib_info(&mut db, &rib_info(64496, 20), &data_file, &stamp, true).unwrap();
+        assert_eq!(
+            stats,
+            IngestStats {
+                written: 1,
+                kept: 0
+            }
+        );
+        let (date, num_v4_pfxs, project, rib_timestamp): (String, u32, String, i64) = db
+            .db
+            .query_row(
+                "SELECT date, num_v4_pfxs, project, rib_timestamp FROM peer_stats",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
         assert_eq!(
-            get_ymd_from_file("peer-stats_rrc16_2022-02-01_1643673600.bz2").unwrap(),
-            (2022, 2, 1)
+            (date.as_str(), num_v4_pfxs, project.as_str(), rib_timestamp),
+            ("2022-02-01", 20, "riperis", 1643673600)
         );
+        assert!(!db.is_db_empty());
+    }
+
+    #[test]
+    fn test_insert_ndjson_file() {
+        let dir =
+            std::env::temp_dir().join(format!("peer-stats-ndjson-test-{}", std::process::id()));
+        let path =
+            dir.join("peer-stats/rrc00/2022/02/peer-stats_rrc00_2022-02-01_1643673600.ndjson");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let data = write_ndjson(&rib_info(64496, 10), "2022-02-01", vec![]).unwrap();
+        std::fs::write(&path, data).unwrap();
+
+        let mut db = PeerStatsDb::new(&None);
+        let pending = prepare_file(&db, &PathLayout::default(), path.to_str().unwrap(), false)
+            .unwrap()
+            .unwrap();
+        let stats = db
+            .insert_data_file(&pending.data_file, &pending.stamp, pending.replace)
+            .unwrap();
         assert_eq!(
-            get_ymd_from_file("/aaa_bbb-ccc/peer-stats_rrc16_2022-02-01_1643673600.bz2").unwrap(),
-            (2022, 2, 1)
+            stats,
+            IngestStats {
+                written: 1,
+                kept: 0
+            }
         );
+        let (collector, asn, num_peers): (String, u32, usize) = db
+            .db
+            .query_row(
+                "SELECT collector, asn, num_peers FROM peer_stats, ingested_files",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((collector.as_str(), asn, num_peers), ("rrc00", 64496, 1));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_selected_date() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let opts = |args: &[&str]| {
+            Opts::parse_from([&["peer-stats-index", "db.sqlite3", "data"], args].concat())
+        };
+        let today = date("2022-03-01");
+
+        let latest = opts(&[]);
+        assert!(is_selected_date(&latest, today, today));
+        assert!(is_selected_date(&latest, date("2022-02-28"), today));
+        // yesterday only at the turn of a month
+        assert!(!is_selected_date(
+            &latest,
+            date("2022-03-14"),
+            date("2022-03-15")
+        ));
+
+        let window = opts(&["--from", "2022-01-10", "--to", "2022-01-20"]);
+        assert!(is_selected_date(&window, date("2022-01-10"), today));
+        assert!(is_selected_date(&window, date("2022-01-20"), today));
+        assert!(!is_selected_date(&window, date("2022-01-21"), today));
+        assert!(!is_selected_date(&window, today, today));
+
+        let open_ended = opts(&["--from", "2022-02-01"]);
+        assert!(is_selected_date(&open_ended, today, today));
+        assert!(is_selected_date(
+            &opts(&["--bootstrap"]),
+            date("2020-01-01"),
+            today
+        ));
+
+        // relative times start with a hyphen
+        let relative = Opts::try_parse_from([
+            "peer-stats-index",
+            "db.sqlite3",
+            "data",
+            "--from",
+            "-7d",
+            "--to",
+            "-1d",
+        ])
+        .unwrap();
+        assert!(relative.from.unwrap() < relative.to.unwrap());
+    }
+
+    #[test]
+    fn test_migrate_unversioned_db() {
+        let path =
+            std::env::temp_dir().join(format!("peer-stats-db-test-{}.db", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute(
+            "INSERT INTO peer_stats (date, collector, ip, asn, num_v4_pfxs, num_v6_pfxs, num_connected_asns) \
+             VALUES ('2022-02-01', 'rrc00', '192.0.2.1', 64496, 10, 0, 1)",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = PeerStatsDb::new(&Some(path.to_str().unwrap().to_string()));
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+        let project: Option<String> = db
+            .db
+            .query_row("SELECT project FROM peer_stats", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(project, None);
+        drop(db);
+        // reopening applies no migrations again
+        let db = PeerStatsDb::new(&Some(path.to_str().unwrap().to_string()));
+        assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len());
+
+        std::fs::remove_file(path).unwrap();
     }
 }
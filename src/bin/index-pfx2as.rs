@@ -1,9 +1,15 @@
+use anyhow::{Context, Result};
 use chrono::{NaiveDate, Utc};
 use clap::Parser;
-use peer_stats::{Prefix2As, Prefix2AsCount};
-use serde_json::json;
-use std::collections::HashMap;
-use std::io::Read;
+use peer_stats::codec::create_compressed_file;
+use peer_stats::format::{for_each_row, write_json, DataSource};
+use peer_stats::{
+    IrrCheckProcessor, IrrRouteDb, OriginStats, OriginStatsProcessor, PathLayout, Prefix2As,
+    Prefix2AsCount,
+};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use tracing::info;
 use walkdir::WalkDir;
@@ -17,27 +23,209 @@ struct Opts {
     /// Path to the data file directory
     data_dir: PathBuf,
 
+    /// Path to a sqlite3 database file to store per-date origin statistics in
+    #[clap(long)]
+    db_file: Option<PathBuf>,
+
+    /// Whether to bootstrap the whole origin statistics database, otherwise, only process the latest
+    #[clap(long, short, requires = "db_file")]
+    bootstrap: bool,
+
     /// whether to print debug
     #[clap(long)]
     debug: bool,
 
     #[clap(long)]
     allow_previous_day: bool,
+
+    /// date of the data files to aggregate, `YYYY-MM-DD`, today by default
+    #[clap(long)]
+    date: Option<NaiveDate>,
+
+    /// layout of the data file paths: `default`, `hive` or a custom template, see
+    /// `peer-stats-bootstrap --layout`
+    #[clap(long, default_value = "default")]
+    layout: PathLayout,
+
+    /// Keep per-collector contributions as a `collectors` array on each aggregated record
+    #[clap(long)]
+    per_collector: bool,
+
+    /// compression level of the output files, whose codec follows the file name extension
+    /// (.bz2, .gz, .zst or uncompressed)
+    #[clap(long)]
+    compression_level: Option<u32>,
+
+    /// Path to a RPSL dump file with IRR route objects to check the aggregated pfx2as against
+    #[clap(long, requires = "irr_output")]
+    irr_file: Option<String>,
+
+    /// Path to the output file of the IRR consistency report
+    #[clap(long, requires = "irr_file")]
+    irr_output: Option<PathBuf>,
+}
+
+/// Contribution of a single collector to an aggregated pfx2as record.
+#[derive(Debug, Clone, Serialize)]
+struct CollectorCount {
+    project: String,
+    collector: String,
+    count: usize,
 }
 
-fn get_ymd_from_file(file_path: &str) -> (i32, u32, u32) {
-    let date_part = file_path.split('_').collect::<Vec<&str>>();
-    let parts = date_part[date_part.len() - 2]
-        .split('-')
-        .collect::<Vec<&str>>();
-    (
-        parts[0].parse::<i32>().unwrap(),
-        parts[1].parse::<u32>().unwrap(),
-        parts[2].parse::<u32>().unwrap(),
-    )
+/// Aggregated pfx2as record with per-collector contributions.
+#[derive(Debug, Clone, Serialize)]
+struct Prefix2AsCollectorsCount {
+    prefix: String,
+    asn: u32,
+    count: usize,
+    collectors: Vec<CollectorCount>,
 }
 
-fn main() {
+/// Sums the pfx2as counts of the current data files, and of each collector with
+/// `--per-collector`.
+struct Pfx2AsAggregator {
+    per_collector: bool,
+    data_map: HashMap<(String, u32), usize>,
+    /// (prefix, asn) -> (project, collector) -> count, only populated with `--per-collector`
+    collectors_map: HashMap<(String, u32), BTreeMap<(String, String), usize>>,
+}
+
+impl Pfx2AsAggregator {
+    fn new(per_collector: bool) -> Self {
+        Pfx2AsAggregator {
+            per_collector,
+            data_map: HashMap::new(),
+            collectors_map: HashMap::new(),
+        }
+    }
+
+    fn process_entry(&mut self, source: &DataSource, pfx2as: &Prefix2AsCount) {
+        let key = (pfx2as.prefix.clone(), pfx2as.asn);
+        if self.per_collector {
+            let collector_count = self
+                .collectors_map
+                .entry(key.clone())
+                .or_default()
+                .entry((source.project.clone(), source.collector.clone()))
+                .or_insert(0);
+            *collector_count += pfx2as.count;
+        }
+        *self.data_map.entry(key).or_insert(0) += pfx2as.count;
+    }
+
+    /// The aggregated records, and the records with per-collector contributions if enabled.
+    fn into_counts(mut self) -> (Vec<Prefix2AsCount>, Vec<Prefix2AsCollectorsCount>) {
+        let counts: Vec<Prefix2AsCount> = self
+            .data_map
+            .into_iter()
+            .map(|((prefix, asn), count)| Prefix2AsCount { prefix, asn, count })
+            .collect();
+        if !self.per_collector {
+            return (counts, vec![]);
+        }
+        let collectors_counts = counts
+            .iter()
+            .map(|pfx2as| {
+                let collectors = self
+                    .collectors_map
+                    .remove(&(pfx2as.prefix.clone(), pfx2as.asn))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|((project, collector), count)| CollectorCount {
+                        project,
+                        collector,
+                        count,
+                    })
+                    .collect();
+                Prefix2AsCollectorsCount {
+                    prefix: pfx2as.prefix.clone(),
+                    asn: pfx2as.asn,
+                    count: pfx2as.count,
+                    collectors,
+                }
+            })
+            .collect();
+        (counts, collectors_counts)
+    }
+}
+
+pub struct OriginStatsDb {
+    db: Connection,
+}
+
+impl OriginStatsDb {
+    pub fn new(db_path: &Option<String>) -> OriginStatsDb {
+        let db = match db_path {
+            Some(p) => Connection::open(p.as_str()).unwrap(),
+            None => Connection::open_in_memory().unwrap(),
+        };
+
+        db.execute(
+            r#"
+        create table if not exists origin_stats (
+        date TEXT,
+        asn INTEGER,
+        num_v4_pfxs INTEGER,
+        num_v6_pfxs INTEGER,
+        v4_space INTEGER,
+        v6_space_48s INTEGER,
+        num_collectors INTEGER,
+        num_observations INTEGER,
+        PRIMARY KEY (date, asn)
+        );
+        "#,
+            [],
+        )
+        .unwrap();
+
+        db.execute(
+            r#"
+        create index if not exists origin_stats_date_index on origin_stats (
+        date DESC
+        );
+        "#,
+            [],
+        )
+        .unwrap();
+
+        OriginStatsDb { db }
+    }
+
+    /// Insert the origin statistics of one date, replacing any existing rows for the date.
+    pub fn insert_origin_stats(&mut self, date: &NaiveDate, stats: &[OriginStats]) {
+        let date = date.format("%Y-%m-%d").to_string();
+        let tx = self.db.transaction().unwrap();
+        tx.execute("DELETE FROM origin_stats WHERE date = ?1", (date.as_str(),))
+            .unwrap();
+        {
+            let mut stmt = tx
+                .prepare(
+                    r#"
+        INSERT INTO origin_stats (date, asn, num_v4_pfxs, num_v6_pfxs, v4_space, v6_space_48s, num_collectors, num_observations)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        "#,
+                )
+                .unwrap();
+            for s in stats {
+                stmt.execute((
+                    date.as_str(),
+                    s.asn,
+                    s.num_v4_pfxs,
+                    s.num_v6_pfxs,
+                    s.v4_space,
+                    s.v6_space_48s,
+                    s.num_collectors,
+                    s.num_observations,
+                ))
+                .unwrap();
+            }
+        }
+        tx.commit().unwrap();
+    }
+}
+
+fn main() -> Result<()> {
     let opts = Opts::parse();
 
     if opts.debug {
@@ -46,60 +234,103 @@ fn main() {
             .init();
     }
 
-    let file_paths = WalkDir::new(opts.data_dir.to_str().unwrap())
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| match e.ok() {
-            Some(entry) => {
-                let path: String = entry.path().to_str().unwrap().to_string();
-                let path_str = path.as_str();
-                if path_str.contains("pfx2as_") && path_str.ends_with(".bz2") {
-                    let (year, month, day) = get_ymd_from_file(path.as_str());
-                    let file_date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
-                    let ts = Utc::now().date_naive();
-                    if file_date == ts {
-                        return Some(path);
-                    }
-                    if opts.allow_previous_day && file_date == ts.pred_opt().unwrap() {
-                        return Some(path);
-                    }
-                }
-                None
-            }
-            None => None,
-        })
-        .collect::<Vec<String>>();
+    let ts = opts.date.unwrap_or_else(|| Utc::now().date_naive());
+    let is_current_date = |file_date: &NaiveDate| -> bool {
+        *file_date == ts || (opts.allow_previous_day && *file_date == ts.pred_opt().unwrap())
+    };
 
-    if file_paths.is_empty() {
+    // group data files by date
+    let mut date_files: BTreeMap<NaiveDate, Vec<String>> = BTreeMap::new();
+    let files = opts.layout.parse_unique(
+        WalkDir::new(opts.data_dir.to_str().unwrap())
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| Some(e.ok()?.path().to_str()?.to_string())),
+    );
+    for (file, path) in files {
+        if file.data_type != "pfx2as" {
+            continue;
+        }
+        if (opts.bootstrap && opts.db_file.is_some()) || is_current_date(&file.date) {
+            date_files.entry(file.date).or_default().push(path);
+        }
+    }
+
+    if date_files.is_empty() {
         info!("no data files found, skipping");
-        return;
+        return Ok(());
     }
 
-    let mut data_map: HashMap<(String, u32), usize> = HashMap::new();
+    let mut db = opts
+        .db_file
+        .as_ref()
+        .map(|p| OriginStatsDb::new(&Some(p.to_str().unwrap().to_string())));
+
+    let mut aggregator = Pfx2AsAggregator::new(opts.per_collector);
+    let mut has_current_files = false;
+
+    for (file_date, file_paths) in date_files {
+        let is_current = is_current_date(&file_date);
+        has_current_files |= is_current;
+        let mut origin_processor = OriginStatsProcessor::new();
+
+        for file in file_paths {
+            info!("processing {}", file.as_str());
+            for_each_row::<Prefix2As, _>(file.as_str(), |source, pfx2as| {
+                if db.is_some() {
+                    origin_processor.process_entry(source.collector.as_str(), &pfx2as);
+                }
+                if !is_current {
+                    return;
+                }
 
-    for file in file_paths {
-        info!("processing {}", file.as_str());
-        let mut data = "".to_string();
-        oneio::get_reader(file.as_str())
-            .unwrap()
-            .read_to_string(&mut data)
+                aggregator.process_entry(source, &pfx2as);
+            })
             .unwrap();
-        let pfx2as_info: Prefix2As = serde_json::from_str(&data).unwrap();
+        }
 
-        for pfx2as in pfx2as_info.pfx2as {
-            let (prefix, asn, count) = (pfx2as.prefix, pfx2as.asn, pfx2as.count);
-            let total_count = data_map.entry((prefix, asn)).or_insert(0);
-            *total_count += count;
+        if let Some(db) = db.as_mut() {
+            let origin_stats = origin_processor.into_origin_stats();
+            info!(
+                "inserting {} origin stats entries for {}",
+                origin_stats.len(),
+                file_date
+            );
+            db.insert_origin_stats(&file_date, &origin_stats);
         }
     }
 
-    let res: Vec<Prefix2AsCount> = data_map
-        .into_iter()
-        .map(|((prefix, asn), count)| Prefix2AsCount { prefix, asn, count })
-        .collect();
+    if !has_current_files {
+        info!("no current date data files found, skipping aggregation");
+        return Ok(());
+    }
+
+    let (res, res_collectors) = aggregator.into_counts();
+
+    let output_file = opts.output_file.to_str().unwrap();
+    let writer = create_compressed_file(output_file, opts.compression_level)?;
+    match opts.per_collector {
+        true => write_json(&res_collectors, writer),
+        false => write_json(&res, writer),
+    }
+    .with_context(|| format!("couldn't write {}", output_file))?;
+
+    if let (Some(irr_file), Some(irr_output)) = (opts.irr_file, opts.irr_output) {
+        info!("loading IRR route objects from {}", irr_file.as_str());
+        let irr = IrrRouteDb::from_file(irr_file.as_str()).unwrap();
+        info!("loaded {} IRR route objects", irr.len());
+
+        let mut irr_processor = IrrCheckProcessor::new(&irr);
+        for pfx2as in &res {
+            irr_processor.process_pfx2as(pfx2as);
+        }
+        let report = irr_processor.into_irr_report();
 
-    let mut writer = oneio::get_writer(opts.output_file.to_str().unwrap()).unwrap();
-    let _ = writer.write_all(serde_json::to_string_pretty(&json!(res)).unwrap().as_ref());
+        let irr_output = irr_output.to_str().unwrap();
+        let writer = create_compressed_file(irr_output, opts.compression_level)?;
+        write_json(&report, writer).with_context(|| format!("couldn't write {}", irr_output))?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -107,14 +338,44 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_get_file_date() {
-        assert_eq!(
-            get_ymd_from_file("as2rel_rrc16_2022-02-01_1643673600.bz2"),
-            (2022, 2, 1)
-        );
-        assert_eq!(
-            get_ymd_from_file("/aaa_bbb-ccc/as2rel_rrc16_2022-02-01_1643673600.bz2"),
-            (2022, 2, 1)
-        );
+    fn test_aggregate_per_collector() {
+        let source = |collector: &str| DataSource {
+            project: "riperis".to_string(),
+            collector: collector.to_string(),
+            rib_dump_url: String::new(),
+        };
+        let count = |prefix: &str, count| Prefix2AsCount {
+            prefix: prefix.to_string(),
+            asn: 65001,
+            count,
+        };
+
+        let mut aggregator = Pfx2AsAggregator::new(true);
+        aggregator.process_entry(&source("rrc00"), &count("192.0.2.0/24", 4));
+        aggregator.process_entry(&source("rrc01"), &count("192.0.2.0/24", 3));
+        aggregator.process_entry(&source("rrc01"), &count("198.51.100.0/24", 2));
+        let (counts, collectors_counts) = aggregator.into_counts();
+        assert_eq!(counts.len(), 2);
+
+        let record = collectors_counts
+            .iter()
+            .find(|c| c.prefix == "192.0.2.0/24")
+            .unwrap();
+        assert_eq!(record.count, 7);
+        let collectors: Vec<(&str, usize)> = record
+            .collectors
+            .iter()
+            .map(|c| (c.collector.as_str(), c.count))
+            .collect();
+        assert_eq!(collectors, vec![("rrc00", 4), ("rrc01", 3)]);
+        let record = collectors_counts
+            .iter()
+            .find(|c| c.prefix == "198.51.100.0/24")
+            .unwrap();
+        assert_eq!((record.count, record.collectors.len()), (2, 1));
+
+        let mut aggregator = Pfx2AsAggregator::new(false);
+        aggregator.process_entry(&source("rrc00"), &count("192.0.2.0/24", 4));
+        assert!(aggregator.into_counts().1.is_empty());
     }
 }
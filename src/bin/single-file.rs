@@ -10,6 +10,14 @@ struct Opts {
     /// File path to a MRT file, local or remote.
     rib_file: PathBuf,
 
+    /// project of the collector, guessed from the file URL if not given
+    #[clap(long)]
+    project: Option<String>,
+
+    /// collector name, guessed from the file URL if not given
+    #[clap(long)]
+    collector: Option<String>,
+
     /// whether to print debug
     #[clap(long)]
     debug: bool,
@@ -44,6 +52,12 @@ fn main() {
             collector = parts[3].to_string();
         }
     };
+    if let Some(p) = opts.project {
+        project = p;
+    }
+    if let Some(c) = opts.collector {
+        collector = c;
+    }
 
     let (peer_stats, _pfx2as, _as2rel) =
         parse_rib_file(file_path, project.as_str(), collector.as_str()).unwrap();
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// Compression codec of the data files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Bzip2,
+    Gzip,
+    Zstd,
+    /// uncompressed
+    None,
+}
+
+impl Codec {
+    /// File name extension of the codec, `None` for uncompressed files.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Codec::Bzip2 => Some("bz2"),
+            Codec::Gzip => Some("gz"),
+            Codec::Zstd => Some("zst"),
+            Codec::None => None,
+        }
+    }
+
+    /// Detect the codec of a file from its file name.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".bz2") {
+            Codec::Bzip2
+        } else if path.ends_with(".gz") {
+            Codec::Gzip
+        } else if path.ends_with(".zst") {
+            Codec::Zstd
+        } else {
+            Codec::None
+        }
+    }
+
+    /// Compression level used if none is given: best compression for bzip2, as written by
+    /// earlier versions, and the library defaults otherwise.
+    pub fn default_level(&self) -> u32 {
+        match self {
+            Codec::Bzip2 => 9,
+            Codec::Gzip => 6,
+            Codec::Zstd => 3,
+            Codec::None => 0,
+        }
+    }
+
+    /// Check that a compression level is supported by the codec.
+    pub fn check_level(&self, level: u32) -> Result<()> {
+        let range = match self {
+            Codec::Bzip2 => 1..=9,
+            Codec::Gzip => 0..=9,
+            Codec::Zstd => 1..=22,
+            Codec::None => 0..=0,
+        };
+        match range.contains(&level) {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "compression level {} not supported by {}, expected {} to {}",
+                level,
+                self,
+                range.start(),
+                range.end()
+            )),
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Codec::Bzip2 => "bz2",
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+            Codec::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bz2" | "bzip2" => Ok(Codec::Bzip2),
+            "gz" | "gzip" => Ok(Codec::Gzip),
+            "zst" | "zstd" => Ok(Codec::Zstd),
+            "none" => Ok(Codec::None),
+            _ => Err(format!(
+                "unknown compression {}, expected bz2, gz, zst or none",
+                s
+            )),
+        }
+    }
+}
+
+/// Writer compressing its data with one of the supported codecs.
+pub enum CompressedWriter<W: Write> {
+    Bzip2(bzip2::write::BzEncoder<W>),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    None(W),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(writer: W, codec: Codec, level: Option<u32>) -> Result<Self> {
+        let level = level.unwrap_or(codec.default_level());
+        codec.check_level(level)?;
+        Ok(match codec {
+            Codec::Bzip2 => CompressedWriter::Bzip2(bzip2::write::BzEncoder::new(
+                writer,
+                bzip2::Compression::new(level),
+            )),
+            Codec::Gzip => CompressedWriter::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::new(level),
+            )),
+            Codec::Zstd => {
+                CompressedWriter::Zstd(zstd::stream::write::Encoder::new(writer, level as i32)?)
+            }
+            Codec::None => CompressedWriter::None(writer),
+        })
+    }
+
+    /// Finish the compressed stream and return the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        let mut writer = match self {
+            CompressedWriter::Bzip2(w) => w.finish()?,
+            CompressedWriter::Gzip(w) => w.finish()?,
+            CompressedWriter::Zstd(w) => w.finish()?,
+            CompressedWriter::None(w) => w,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            CompressedWriter::Bzip2(w) => w.write(buf),
+            CompressedWriter::Gzip(w) => w.write(buf),
+            CompressedWriter::Zstd(w) => w.write(buf),
+            CompressedWriter::None(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            CompressedWriter::Bzip2(w) => w.flush(),
+            CompressedWriter::Gzip(w) => w.flush(),
+            CompressedWriter::Zstd(w) => w.flush(),
+            CompressedWriter::None(w) => w.flush(),
+        }
+    }
+}
+
+/// Create a file compressed with the codec matching its file name extension.
+pub fn create_compressed_file(path: &str, level: Option<u32>) -> Result<CompressedWriter<File>> {
+    let file = File::create(path).with_context(|| format!("couldn't create {}", path))?;
+    CompressedWriter::new(file, Codec::from_path(path), level)
+}
+
+/// Wrap a reader to decompress data written with the given codec.
+pub fn decompressed_reader<'a, R: Read + 'a>(
+    reader: R,
+    codec: Codec,
+) -> Result<Box<dyn Read + 'a>> {
+    Ok(match codec {
+        Codec::Bzip2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+        Codec::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        Codec::None => Box::new(reader),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let data = b"{\"prefix\":\"192.0.2.0/24\",\"asn\":64496}\n".repeat(100);
+        for codec in [Codec::Bzip2, Codec::Gzip, Codec::Zstd, Codec::None] {
+            let mut writer = CompressedWriter::new(vec![], codec, None).unwrap();
+            writer.write_all(&data).unwrap();
+            let compressed = writer.finish().unwrap();
+
+            let mut decompressed = vec![];
+            decompressed_reader(compressed.as_slice(), codec)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+
+        assert_eq!(
+            Codec::from_path("pfx2as_rrc00_2022-02-01_1643673600.zst"),
+            Codec::Zstd
+        );
+        assert_eq!("gzip".parse::<Codec>().unwrap(), Codec::Gzip);
+        assert!(CompressedWriter::new(vec![], Codec::Bzip2, Some(12)).is_err());
+    }
+}
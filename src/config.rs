@@ -0,0 +1,250 @@
+use crate::scheduler::parse_size;
+use crate::{Codec, OutputFormat, PathLayout, RibDataType, SelectionPolicy, Tier1Config};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Bootstrap settings read from a TOML file. All settings are optional, command-line flags and
+/// environment variables take precedence over them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BootstrapConfig {
+    pub source: SourceConfig,
+    pub collectors: CollectorsConfig,
+    pub time: TimeConfig,
+    pub output: OutputConfig,
+    pub limits: LimitsConfig,
+    pub watch: WatchConfig,
+    pub tier1: Tier1Config,
+}
+
+/// Where dump files are found, the `[source]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    pub broker_url: Option<String>,
+    pub local_archive: Option<PathBuf>,
+    pub project_mapping: Option<PathBuf>,
+}
+
+/// Collectors and projects to process, the `[collectors]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectorsConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+/// Time range and dump selection, the `[time]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeConfig {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// process the given number of months before the current one in monthly chunks
+    pub backfill_months: Option<u32>,
+    /// process the given number of days before today until now
+    pub last_days: Option<u32>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub select: Option<SelectionPolicy>,
+}
+
+/// Data files to produce, the `[output]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub dir: Option<PathBuf>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub layout: Option<PathLayout>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub format: Option<OutputFormat>,
+    #[serde(deserialize_with = "parse_opt")]
+    pub compression: Option<Codec>,
+    pub compression_level: Option<u32>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub types: Vec<RibDataType>,
+}
+
+/// Resource limits and retries, the `[limits]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub threads: Option<usize>,
+    #[serde(deserialize_with = "parse_size_opt")]
+    pub max_memory: Option<u64>,
+    pub retries: Option<u32>,
+    pub retry_backoff: Option<u64>,
+    /// timeout per dump file in minutes
+    pub timeout: Option<u64>,
+    /// share of failed RIB dumps above which the run fails
+    pub max_failure_ratio: Option<f64>,
+}
+
+/// Polling and indexing of the watch mode, the `[watch]` section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// time between polls in minutes
+    pub poll_interval: Option<u64>,
+    /// how far back to look for late dumps in hours
+    pub lookback: Option<i64>,
+    pub index_commands: Vec<String>,
+}
+
+impl BootstrapConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read config file {}", path.display()))?;
+        content
+            .parse()
+            .with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Check the values that the corresponding flags restrict to a range.
+    fn validate(&self) -> Result<()> {
+        if matches!(self.limits.max_failure_ratio, Some(ratio) if !(0.0..=1.0).contains(&ratio)) {
+            bail!("limits.max_failure_ratio must be between 0 and 1");
+        }
+        if self.watch.poll_interval == Some(0) {
+            bail!("watch.poll_interval must be at least 1 minute");
+        }
+        if matches!(self.watch.lookback, Some(lookback) if lookback < 0) {
+            bail!("watch.lookback must not be negative");
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BootstrapConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let config: Self = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Deserialize an optional value from its string form.
+fn parse_opt<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Deserialize a list of values from their string forms.
+fn parse_vec<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Deserialize a memory size given as number of bytes or string such as `48G`.
+fn parse_size_opt<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(n)) => Ok(Some(n)),
+        Some(Size::Text(s)) => parse_size(s.as_str())
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: BootstrapConfig = r#"
+            [source]
+            broker_url = "http://localhost:40064"
+
+            [collectors]
+            include = ["rrc*", "route-views2"]
+            exclude = ["rrc2?"]
+
+            [time]
+            backfill_months = 12
+            select = "first-per-day"
+
+            [output]
+            dir = "/data/peer-stats"
+            format = "parquet"
+            types = ["peer-stats", "pfx2as"]
+
+            [limits]
+            threads = 8
+            max_memory = "48G"
+
+            [watch]
+            index_commands = ["as2rel-index /data/as2rel {output_dir} --date {date}"]
+
+            [tier1]
+            true_tier1 = [174, 3356]
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.collectors.include, vec!["rrc*", "route-views2"]);
+        assert_eq!(config.time.backfill_months, Some(12));
+        assert_eq!(config.time.select, Some(SelectionPolicy::FirstPerDay));
+        assert_eq!(config.output.format, Some(OutputFormat::Parquet));
+        assert_eq!(
+            config.output.types,
+            vec![RibDataType::PeerStats, RibDataType::Pfx2As]
+        );
+        assert_eq!(config.limits.max_memory, Some(48 << 30));
+        assert_eq!(config.watch.index_commands.len(), 1);
+        assert_eq!(config.tier1.true_tier1, vec![174, 3356]);
+        // candidates not given keep their defaults
+        assert_eq!(
+            config.tier1.candidate_tier1_v4,
+            Tier1Config::default().candidate_tier1_v4
+        );
+        assert_eq!(config.output.layout, None);
+
+        assert_eq!(
+            "".parse::<BootstrapConfig>().unwrap(),
+            BootstrapConfig::default()
+        );
+        assert!("[output]\nformat = \"csv\""
+            .parse::<BootstrapConfig>()
+            .is_err());
+        assert!("[limits]\nthread = 8".parse::<BootstrapConfig>().is_err());
+        assert!("[watch]\npoll_interval = 0"
+            .parse::<BootstrapConfig>()
+            .is_err());
+        assert!("[watch]\nlookback = -1".parse::<BootstrapConfig>().is_err());
+        assert!("[limits]\nmax_failure_ratio = 1.5"
+            .parse::<BootstrapConfig>()
+            .is_err());
+        assert!("[limits]\nmax_failure_ratio = -0.1"
+            .parse::<BootstrapConfig>()
+            .is_err());
+        assert!("[watch]\nlookback = 0".parse::<BootstrapConfig>().is_ok());
+    }
+}
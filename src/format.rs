@@ -0,0 +1,870 @@
+use crate::codec::{Codec, CompressedWriter};
+use crate::{As2Rel, As2RelCount, PeerInfo, Prefix2As, Prefix2AsCount, RibPeerInfo};
+use anyhow::{anyhow, Context, Result};
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt32Array, UInt64Array, UInt8Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// File format of the RIB-based data files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// pretty-printed JSON
+    #[default]
+    Json,
+    /// Parquet with one row per entry and the RIB dump information as file metadata
+    Parquet,
+    /// newline-delimited JSON, one entry per line with the RIB dump information repeated on
+    /// every line
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// Detect the format of a data file from its file name, ignoring a codec extension.
+    pub fn from_path(path: &str) -> Self {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let name = [".bz2", ".gz", ".zst"]
+            .iter()
+            .find_map(|ext| file_name.strip_suffix(ext))
+            .unwrap_or(file_name);
+        if name.ends_with(".parquet") {
+            OutputFormat::Parquet
+        } else if name.ends_with(".ndjson") {
+            OutputFormat::Ndjson
+        } else {
+            OutputFormat::Json
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Ndjson => "ndjson",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!(
+                "unknown output format {}, expected json, ndjson or parquet",
+                s
+            )),
+        }
+    }
+}
+
+/// Format and compression of written data files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    /// compression of JSON and NDJSON files, or the internal compression of Parquet files
+    pub codec: Codec,
+    /// compression level, the codec's default if not given
+    pub level: Option<u32>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            format: OutputFormat::Json,
+            codec: Codec::Bzip2,
+            level: None,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Build output options, defaulting to zstd for Parquet and bzip2 otherwise.
+    pub fn new(format: OutputFormat, codec: Option<Codec>, level: Option<u32>) -> Result<Self> {
+        let codec = codec.unwrap_or(match format {
+            OutputFormat::Parquet => Codec::Zstd,
+            _ => Codec::Bzip2,
+        });
+        if format == OutputFormat::Parquet && codec == Codec::Bzip2 {
+            return Err(anyhow!("bzip2 compression is not supported for parquet"));
+        }
+        if let Some(level) = level {
+            codec.check_level(level)?;
+        }
+        Ok(OutputOptions {
+            format,
+            codec,
+            level,
+        })
+    }
+
+    /// File name extension of data files, e.g. `bz2` for bzip2-compressed JSON (as written by
+    /// earlier versions), `ndjson.zst` or `parquet`.
+    pub fn extension(&self) -> String {
+        match (self.format, self.codec.extension()) {
+            (OutputFormat::Parquet, _) => "parquet".to_string(),
+            (OutputFormat::Json, Some(ext)) => ext.to_string(),
+            (OutputFormat::Json, None) => "json".to_string(),
+            (OutputFormat::Ndjson, Some(ext)) => format!("ndjson.{}", ext),
+            (OutputFormat::Ndjson, None) => "ndjson".to_string(),
+        }
+    }
+}
+
+/// Strip the format and/or codec extension of a data file name.
+fn strip_data_extension(file_name: &str) -> Option<&str> {
+    let mut name = file_name;
+    for ext in [".bz2", ".gz", ".zst"] {
+        if let Some(n) = name.strip_suffix(ext) {
+            name = n;
+            break;
+        }
+    }
+    let is_compressed = name != file_name;
+    match [".json", ".ndjson", ".parquet"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+    {
+        Some(n) => Some(n),
+        // bare timestamps are only used for compressed JSON files
+        None if is_compressed => Some(name),
+        None => None,
+    }
+}
+
+/// Whether an extension (without leading dot), e.g. `bz2` or `ndjson.zst`, is one of a data file.
+pub fn is_data_extension(extension: &str) -> bool {
+    strip_data_extension(format!(".{}", extension).as_str()) == Some("")
+}
+
+/// Whether a file name looks like a data file in any of the supported formats and codecs,
+/// i.e. `<type>_<collector>_<date>_<timestamp>` followed by a format and/or codec extension.
+pub fn is_data_file(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let name = match strip_data_extension(file_name) {
+        Some(n) => n,
+        None => return false,
+    };
+    match name.rsplit_once('_') {
+        Some((_, ts)) => !ts.is_empty() && ts.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Project, collector and RIB dump URL of a data file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataSource {
+    pub project: String,
+    pub collector: String,
+    pub rib_dump_url: String,
+}
+
+/// Data made of a list of entries from a single RIB dump.
+pub trait RowData: Sized {
+    type Row: Serialize + DeserializeOwned;
+
+    /// (project, collector, rib_dump_url)
+    fn source(&self) -> (&str, &str, &str);
+
+    fn rows(&self) -> Vec<&Self::Row>;
+
+    fn into_rows(self) -> Vec<Self::Row>;
+
+    fn from_rows(source: DataSource, rows: Vec<Self::Row>) -> Self;
+}
+
+/// Data that can be stored as a table with one row per entry.
+///
+/// The project, collector and RIB dump URL of the data are kept as file metadata.
+pub trait ColumnarData: RowData {
+    fn schema() -> Schema;
+
+    fn to_record_batch(&self) -> Result<RecordBatch>;
+
+    fn from_record_batches(
+        project: String,
+        collector: String,
+        rib_dump_url: String,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Self>;
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<T>())
+        .ok_or_else(|| anyhow!("missing or mistyped column {}", name))
+}
+
+impl RowData for RibPeerInfo {
+    type Row = PeerInfo;
+
+    fn source(&self) -> (&str, &str, &str) {
+        (&self.project, &self.collector, &self.rib_dump_url)
+    }
+
+    fn rows(&self) -> Vec<&PeerInfo> {
+        let mut peers: Vec<&PeerInfo> = self.peers.values().collect();
+        peers.sort_by_key(|p| p.ip);
+        peers
+    }
+
+    fn into_rows(self) -> Vec<PeerInfo> {
+        self.peers.into_values().collect()
+    }
+
+    fn from_rows(source: DataSource, rows: Vec<PeerInfo>) -> Self {
+        RibPeerInfo {
+            project: source.project,
+            collector: source.collector,
+            rib_dump_url: source.rib_dump_url,
+            peers: rows.into_iter().map(|p| (p.ip, p)).collect(),
+        }
+    }
+}
+
+impl ColumnarData for RibPeerInfo {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("ip", DataType::Utf8, false),
+            Field::new("asn", DataType::UInt32, false),
+            Field::new("num_v4_pfxs", DataType::UInt64, false),
+            Field::new("num_v6_pfxs", DataType::UInt64, false),
+            Field::new("num_connected_asns", DataType::UInt64, false),
+        ])
+    }
+
+    fn to_record_batch(&self) -> Result<RecordBatch> {
+        let peers = self.rows();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                peers.iter().map(|p| p.ip.to_string()),
+            )),
+            Arc::new(UInt32Array::from_iter_values(peers.iter().map(|p| p.asn))),
+            Arc::new(UInt64Array::from_iter_values(
+                peers.iter().map(|p| p.num_v4_pfxs as u64),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                peers.iter().map(|p| p.num_v6_pfxs as u64),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                peers.iter().map(|p| p.num_connected_asns as u64),
+            )),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(Self::schema()), columns)?)
+    }
+
+    fn from_record_batches(
+        project: String,
+        collector: String,
+        rib_dump_url: String,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Self> {
+        let mut peers = HashMap::new();
+        for batch in &batches {
+            let ip = column::<StringArray>(batch, "ip")?;
+            let asn = column::<UInt32Array>(batch, "asn")?;
+            let num_v4_pfxs = column::<UInt64Array>(batch, "num_v4_pfxs")?;
+            let num_v6_pfxs = column::<UInt64Array>(batch, "num_v6_pfxs")?;
+            let num_connected_asns = column::<UInt64Array>(batch, "num_connected_asns")?;
+            for i in 0..batch.num_rows() {
+                let ip: IpAddr = ip.value(i).parse()?;
+                peers.insert(
+                    ip,
+                    PeerInfo {
+                        ip,
+                        asn: asn.value(i),
+                        num_v4_pfxs: num_v4_pfxs.value(i) as usize,
+                        num_v6_pfxs: num_v6_pfxs.value(i) as usize,
+                        num_connected_asns: num_connected_asns.value(i) as usize,
+                    },
+                );
+            }
+        }
+        Ok(RibPeerInfo {
+            project,
+            collector,
+            rib_dump_url,
+            peers,
+        })
+    }
+}
+
+impl RowData for Prefix2As {
+    type Row = Prefix2AsCount;
+
+    fn source(&self) -> (&str, &str, &str) {
+        (&self.project, &self.collector, &self.rib_dump_url)
+    }
+
+    fn rows(&self) -> Vec<&Prefix2AsCount> {
+        self.pfx2as.iter().collect()
+    }
+
+    fn into_rows(self) -> Vec<Prefix2AsCount> {
+        self.pfx2as
+    }
+
+    fn from_rows(source: DataSource, rows: Vec<Prefix2AsCount>) -> Self {
+        Prefix2As {
+            project: source.project,
+            collector: source.collector,
+            rib_dump_url: source.rib_dump_url,
+            pfx2as: rows,
+        }
+    }
+}
+
+impl ColumnarData for Prefix2As {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("prefix", DataType::Utf8, false),
+            Field::new("asn", DataType::UInt32, false),
+            Field::new("count", DataType::UInt64, false),
+        ])
+    }
+
+    fn to_record_batch(&self) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                self.pfx2as.iter().map(|p| p.prefix.as_str()),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                self.pfx2as.iter().map(|p| p.asn),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                self.pfx2as.iter().map(|p| p.count as u64),
+            )),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(Self::schema()), columns)?)
+    }
+
+    fn from_record_batches(
+        project: String,
+        collector: String,
+        rib_dump_url: String,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Self> {
+        let mut pfx2as = vec![];
+        for batch in &batches {
+            let prefix = column::<StringArray>(batch, "prefix")?;
+            let asn = column::<UInt32Array>(batch, "asn")?;
+            let count = column::<UInt64Array>(batch, "count")?;
+            for i in 0..batch.num_rows() {
+                pfx2as.push(Prefix2AsCount {
+                    prefix: prefix.value(i).to_string(),
+                    asn: asn.value(i),
+                    count: count.value(i) as usize,
+                });
+            }
+        }
+        Ok(Prefix2As {
+            project,
+            collector,
+            rib_dump_url,
+            pfx2as,
+        })
+    }
+}
+
+impl RowData for As2Rel {
+    type Row = As2RelCount;
+
+    fn source(&self) -> (&str, &str, &str) {
+        (&self.project, &self.collector, &self.rib_dump_url)
+    }
+
+    fn rows(&self) -> Vec<&As2RelCount> {
+        self.as2rel.iter().collect()
+    }
+
+    fn into_rows(self) -> Vec<As2RelCount> {
+        self.as2rel
+    }
+
+    fn from_rows(source: DataSource, rows: Vec<As2RelCount>) -> Self {
+        As2Rel {
+            project: source.project,
+            collector: source.collector,
+            rib_dump_url: source.rib_dump_url,
+            as2rel: rows,
+        }
+    }
+}
+
+impl ColumnarData for As2Rel {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("asn1", DataType::UInt32, false),
+            Field::new("asn2", DataType::UInt32, false),
+            Field::new("rel", DataType::UInt8, false),
+            Field::new("paths_count", DataType::UInt64, false),
+            Field::new("peers_count", DataType::UInt64, false),
+        ])
+    }
+
+    fn to_record_batch(&self) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt32Array::from_iter_values(
+                self.as2rel.iter().map(|r| r.asn1),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                self.as2rel.iter().map(|r| r.asn2),
+            )),
+            Arc::new(UInt8Array::from_iter_values(
+                self.as2rel.iter().map(|r| r.rel),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                self.as2rel.iter().map(|r| r.paths_count as u64),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                self.as2rel.iter().map(|r| r.peers_count as u64),
+            )),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(Self::schema()), columns)?)
+    }
+
+    fn from_record_batches(
+        project: String,
+        collector: String,
+        rib_dump_url: String,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Self> {
+        let mut as2rel = vec![];
+        for batch in &batches {
+            let asn1 = column::<UInt32Array>(batch, "asn1")?;
+            let asn2 = column::<UInt32Array>(batch, "asn2")?;
+            let rel = column::<UInt8Array>(batch, "rel")?;
+            let paths_count = column::<UInt64Array>(batch, "paths_count")?;
+            let peers_count = column::<UInt64Array>(batch, "peers_count")?;
+            for i in 0..batch.num_rows() {
+                as2rel.push(As2RelCount {
+                    asn1: asn1.value(i),
+                    asn2: asn2.value(i),
+                    rel: rel.value(i),
+                    paths_count: paths_count.value(i) as usize,
+                    peers_count: peers_count.value(i) as usize,
+                });
+            }
+        }
+        Ok(As2Rel {
+            project,
+            collector,
+            rib_dump_url,
+            as2rel,
+        })
+    }
+}
+
+/// Write data as a Parquet file, returning the underlying writer.
+///
+/// The codec is used as the Parquet column compression; bzip2 is not supported.
+pub fn write_parquet<T: ColumnarData, W: Write + Send>(
+    data: &T,
+    codec: Codec,
+    level: Option<u32>,
+    writer: W,
+) -> Result<W> {
+    let (project, collector, rib_dump_url) = data.source();
+    let metadata = [
+        ("project", project),
+        ("collector", collector),
+        ("rib_dump_url", rib_dump_url),
+    ]
+    .into_iter()
+    .map(|(k, v)| KeyValue::new(k.to_string(), v.to_string()))
+    .collect();
+    let level = level.unwrap_or(codec.default_level());
+    let compression = match codec {
+        Codec::Zstd => Compression::ZSTD(ZstdLevel::try_new(level as i32)?),
+        Codec::Gzip => Compression::GZIP(GzipLevel::try_new(level)?),
+        Codec::None => Compression::UNCOMPRESSED,
+        Codec::Bzip2 => return Err(anyhow!("bzip2 compression is not supported for parquet")),
+    };
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        .set_key_value_metadata(Some(metadata))
+        .build();
+
+    let batch = data.to_record_batch()?;
+    let mut parquet_writer = ArrowWriter::try_new(writer, batch.schema(), Some(props))?;
+    parquet_writer.write(&batch)?;
+    Ok(parquet_writer.into_inner()?)
+}
+
+/// Read data from a Parquet file written by [write_parquet].
+pub fn read_parquet<T: ColumnarData>(file: File) -> Result<T> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let metadata: HashMap<String, String> = builder
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .map(|kvs| {
+            kvs.iter()
+                .filter_map(|kv| Some((kv.key.clone(), kv.value.clone()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let get = |key: &str| metadata.get(key).cloned().unwrap_or_default();
+    let batches = builder.build()?.collect::<Result<Vec<RecordBatch>, _>>()?;
+    T::from_record_batches(
+        get("project"),
+        get("collector"),
+        get("rib_dump_url"),
+        batches,
+    )
+}
+
+/// Read all row groups of a Parquet file to make sure it is complete and valid.
+pub fn verify_parquet(file: File) -> Result<()> {
+    for batch in ParquetRecordBatchReaderBuilder::try_new(file)?.build()? {
+        batch?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct NdjsonRecordRef<'a, R> {
+    project: &'a str,
+    collector: &'a str,
+    date: &'a str,
+    rib_dump_url: &'a str,
+    #[serde(flatten)]
+    row: &'a R,
+}
+
+#[derive(Deserialize)]
+struct NdjsonRecord<R> {
+    project: String,
+    collector: String,
+    rib_dump_url: String,
+    #[serde(flatten)]
+    row: R,
+}
+
+/// Write data as newline-delimited JSON, one entry per line with project, collector, date
+/// (`YYYY-MM-DD`) and RIB dump URL repeated on every line. Returns the underlying writer.
+pub fn write_ndjson<T: RowData, W: Write>(data: &T, date: &str, writer: W) -> Result<W> {
+    let (project, collector, rib_dump_url) = data.source();
+    let mut writer = BufWriter::with_capacity(128 * 1024, writer);
+    for row in data.rows() {
+        let record = NdjsonRecordRef {
+            project,
+            collector,
+            date,
+            rib_dump_url,
+            row,
+        };
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Write data as pretty-printed JSON to a compressed writer, serializing it directly instead of
+/// building the whole document in memory. Returns the underlying writer.
+pub fn write_json<T: Serialize + ?Sized, W: Write>(
+    data: &T,
+    writer: CompressedWriter<W>,
+) -> Result<W> {
+    let mut writer = BufWriter::with_capacity(128 * 1024, writer);
+    serde_json::to_writer_pretty(&mut writer, data)?;
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+/// Write data in the given format and compression.
+pub fn write_data<T: ColumnarData + Serialize, W: Write + Send>(
+    data: &T,
+    options: &OutputOptions,
+    date: &str,
+    writer: W,
+) -> Result<W> {
+    match options.format {
+        OutputFormat::Json => {
+            let compressor = CompressedWriter::new(writer, options.codec, options.level)?;
+            write_json(data, compressor)
+        }
+        OutputFormat::Ndjson => {
+            let compressor = CompressedWriter::new(writer, options.codec, options.level)?;
+            write_ndjson(data, date, compressor)?.finish()
+        }
+        OutputFormat::Parquet => write_parquet(data, options.codec, options.level, writer),
+    }
+}
+
+/// Call `f` with every entry of a data file in any of the supported formats.
+///
+/// NDJSON files are read line by line without loading the whole file into memory.
+pub fn for_each_row<T, F>(path: &str, mut f: F) -> Result<()>
+where
+    T: ColumnarData + DeserializeOwned,
+    F: FnMut(&DataSource, T::Row),
+{
+    if OutputFormat::from_path(path) != OutputFormat::Ndjson {
+        let data: T = read_data_file(path)?;
+        let (project, collector, rib_dump_url) = data.source();
+        let source = DataSource {
+            project: project.to_string(),
+            collector: collector.to_string(),
+            rib_dump_url: rib_dump_url.to_string(),
+        };
+        for row in data.into_rows() {
+            f(&source, row);
+        }
+        return Ok(());
+    }
+
+    let reader = BufReader::new(oneio::get_reader(path)?);
+    let mut source = DataSource::default();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let record: NdjsonRecord<T::Row> = serde_json::from_str(&line)
+            .with_context(|| format!("couldn't parse line {} of {}", idx + 1, path))?;
+        if source.collector != record.collector
+            || source.project != record.project
+            || source.rib_dump_url != record.rib_dump_url
+        {
+            source = DataSource {
+                project: record.project,
+                collector: record.collector,
+                rib_dump_url: record.rib_dump_url,
+            };
+        }
+        f(&source, record.row);
+    }
+    Ok(())
+}
+
+/// Read a data file in any of the supported formats, detected from the file name.
+pub fn read_data_file<T: ColumnarData + DeserializeOwned>(path: &str) -> Result<T> {
+    match OutputFormat::from_path(path) {
+        OutputFormat::Parquet => {
+            let file = File::open(path).with_context(|| format!("couldn't open {}", path))?;
+            read_parquet(file).with_context(|| format!("couldn't read {}", path))
+        }
+        OutputFormat::Json => {
+            let mut data = "".to_string();
+            oneio::get_reader(path)?.read_to_string(&mut data)?;
+            serde_json::from_str(&data).with_context(|| format!("couldn't parse {}", path))
+        }
+        OutputFormat::Ndjson => {
+            let mut source = DataSource::default();
+            let mut rows = vec![];
+            for_each_row::<T, _>(path, |s, row| {
+                if source != *s {
+                    source = s.clone();
+                }
+                rows.push(row);
+            })?;
+            Ok(T::from_rows(source, rows))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        let from_path = OutputFormat::from_path;
+        assert_eq!(
+            from_path("pfx2as_rrc00_2022-02-01_1643673600.ndjson.zst"),
+            OutputFormat::Ndjson
+        );
+        assert_eq!(
+            from_path("/data/pfx2as_rrc00_2022-02-01_1643673600.ndjson"),
+            OutputFormat::Ndjson
+        );
+        assert_eq!(
+            from_path("/data.ndjson/pfx2as_rrc00_2022-02-01_1643673600.bz2"),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            from_path("/data.ndjson/pfx2as_rrc00_2022-02-01_1643673600.json"),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            from_path("/data.parquet/pfx2as_rrc00_2022-02-01_1643673600.parquet"),
+            OutputFormat::Parquet
+        );
+    }
+
+    #[test]
+    fn test_parquet_roundtrip() {
+        let as2rel = As2Rel {
+            project: "riperis".to_string(),
+            collector: "rrc00".to_string(),
+            rib_dump_url: "https://data.ris.ripe.net/rrc00/2022.02/bview.20220201.0000.gz"
+                .to_string(),
+            as2rel: vec![As2RelCount {
+                asn1: 174,
+                asn2: 13335,
+                rel: 1,
+                paths_count: 20,
+                peers_count: 3,
+            }],
+        };
+        let path = std::env::temp_dir().join(format!(
+            "as2rel_rrc00-{}_2022-02-01_1643673600.parquet",
+            std::process::id()
+        ));
+
+        let file = write_parquet(&as2rel, Codec::Zstd, None, File::create(&path).unwrap()).unwrap();
+        file.sync_all().unwrap();
+        let path_str = path.to_str().unwrap();
+        assert_eq!(OutputFormat::from_path(path_str), OutputFormat::Parquet);
+        assert!(is_data_file(path_str));
+        assert!(is_data_file("/data/pfx2as_rrc00_2022-02-01_1643673600.bz2"));
+        assert!(is_data_file(
+            "pfx2as_rrc00_2022-02-01_1643673600.ndjson.zst"
+        ));
+        assert!(is_data_file("pfx2as_rrc00_2022-02-01_1643673600.json"));
+        assert!(!is_data_file("pfx2as_rrc00_2022-02-01_1643673600.bz2.tmp"));
+        assert!(!is_data_file("pfx2as-latest.json.bz2"));
+
+        let read: As2Rel = read_data_file(path_str).unwrap();
+        assert_eq!(read.collector, "rrc00");
+        assert_eq!(read.rib_dump_url, as2rel.rib_dump_url);
+        assert_eq!(read.as2rel.len(), 1);
+        assert_eq!(read.as2rel[0].asn2, 13335);
+        assert_eq!(read.as2rel[0].peers_count, 3);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ndjson_rows() {
+        let pfx2as = Prefix2As {
+            project: "route-views".to_string(),
+            collector: "route-views2".to_string(),
+            rib_dump_url: "rib.20220201.0000.bz2".to_string(),
+            pfx2as: vec![
+                Prefix2AsCount {
+                    prefix: "192.0.2.0/24".to_string(),
+                    asn: 64496,
+                    count: 10,
+                },
+                Prefix2AsCount {
+                    prefix: "2001:db8::/32".to_string(),
+                    asn: 64497,
+                    count: 2,
+                },
+            ],
+        };
+        let data = write_ndjson(&pfx2as, "2022-02-01", vec![]).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&data).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"date\":\"2022-02-01\""));
+        assert!(lines[1].contains("\"collector\":\"route-views2\""));
+
+        let path = std::env::temp_dir().join(format!(
+            "pfx2as_route-views2_2022-02-01_1643673600-{}.ndjson",
+            std::process::id()
+        ));
+        std::fs::write(&path, &data).unwrap();
+        let mut rows = vec![];
+        for_each_row::<Prefix2As, _>(path.to_str().unwrap(), |source, row| {
+            assert_eq!(source.project, "route-views");
+            rows.push(row);
+        })
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].asn, 64497);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ndjson_roundtrip() {
+        let path = |name: &str| {
+            std::env::temp_dir().join(format!(
+                "{}_rrc00_2022-02-01_1643673600-{}.ndjson",
+                name,
+                std::process::id()
+            ))
+        };
+        let rib_dump_url = "https://data.ris.ripe.net/rrc00/2022.02/bview.20220201.0000.gz";
+
+        let peer = |ip: &str, asn| PeerInfo {
+            ip: ip.parse().unwrap(),
+            asn,
+            num_v4_pfxs: 10,
+            num_v6_pfxs: 2,
+            num_connected_asns: 3,
+        };
+        let peers = [peer("192.0.2.1", 64496), peer("2001:db8::1", 64497)];
+        let peer_stats = RibPeerInfo {
+            project: "riperis".to_string(),
+            collector: "rrc00".to_string(),
+            rib_dump_url: rib_dump_url.to_string(),
+            peers: peers.iter().map(|p| (p.ip, p.clone())).collect(),
+        };
+        let peer_stats_path = path("peer-stats");
+        let data = write_ndjson(&peer_stats, "2022-02-01", vec![]).unwrap();
+        std::fs::write(&peer_stats_path, data).unwrap();
+        let read: RibPeerInfo = read_data_file(peer_stats_path.to_str().unwrap()).unwrap();
+        assert_eq!(read.collector, "rrc00");
+        assert_eq!(read.rib_dump_url, rib_dump_url);
+        assert_eq!(read.peers.len(), 2);
+        for peer in &peers {
+            let read_peer = &read.peers[&peer.ip];
+            assert_eq!(read_peer.ip, peer.ip);
+            assert_eq!(read_peer.asn, peer.asn);
+            assert_eq!(read_peer.num_v6_pfxs, 2);
+            assert_eq!(read_peer.num_connected_asns, 3);
+        }
+        std::fs::remove_file(peer_stats_path).unwrap();
+
+        let as2rel = As2Rel {
+            project: "riperis".to_string(),
+            collector: "rrc00".to_string(),
+            rib_dump_url: rib_dump_url.to_string(),
+            as2rel: vec![As2RelCount {
+                asn1: 174,
+                asn2: 13335,
+                rel: 1,
+                paths_count: 20,
+                peers_count: 3,
+            }],
+        };
+        let as2rel_path = path("as2rel");
+        let data = write_ndjson(&as2rel, "2022-02-01", vec![]).unwrap();
+        std::fs::write(&as2rel_path, data).unwrap();
+        let read: As2Rel = read_data_file(as2rel_path.to_str().unwrap()).unwrap();
+        assert_eq!(read.project, "riperis");
+        assert_eq!(read.as2rel.len(), 1);
+        assert_eq!(
+            (
+                read.as2rel[0].asn1,
+                read.as2rel[0].asn2,
+                read.as2rel[0].rel,
+                read.as2rel[0].paths_count,
+                read.as2rel[0].peers_count
+            ),
+            (174, 13335, 1, 20, 3)
+        );
+        std::fs::remove_file(as2rel_path).unwrap();
+    }
+}
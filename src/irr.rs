@@ -0,0 +1,293 @@
+use crate::pfx2as::Prefix2AsCount;
+use anyhow::Result;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// IRR consistency status of an observed (prefix, origin) pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IrrStatus {
+    /// a route object exists for the prefix with the observed origin
+    Match,
+    /// route objects exist for the prefix, but none with the observed origin
+    Mismatch,
+    /// no route object exists for the prefix
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pfx2AsIrrCount {
+    pub prefix: String,
+    pub asn: u32,
+    pub count: usize,
+    pub status: IrrStatus,
+    /// origins registered in IRR for the exact prefix
+    pub irr_origins: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OriginIrrSummary {
+    pub asn: u32,
+    pub num_match: usize,
+    pub num_mismatch: usize,
+    pub num_missing: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrrReport {
+    /// pfx2as entries annotated with their IRR status
+    pub pfx2as: Vec<Pfx2AsIrrCount>,
+    /// per-origin summary of the IRR status counts
+    pub origins: Vec<OriginIrrSummary>,
+}
+
+/// Route objects loaded from an RPSL dump, indexed by exact prefix.
+#[derive(Debug, Clone, Default)]
+pub struct IrrRouteDb {
+    routes: HashMap<IpNet, HashSet<u32>>,
+}
+
+/// Parse an RPSL origin value such as `AS13335`, ignoring case and trailing comments.
+fn parse_origin(value: &str) -> Option<u32> {
+    let value = value.split('#').next()?.trim();
+    if !value.get(..2)?.eq_ignore_ascii_case("as") {
+        return None;
+    }
+    value[2..].parse::<u32>().ok()
+}
+
+impl IrrRouteDb {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Load route objects from a local or remote RPSL dump file.
+    ///
+    /// Compressed files (e.g. `radb.db.gz`, `ripe.db.route.gz`) are handled by `oneio`.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let mut db = Self::new();
+        db.load_rpsl(oneio::get_reader(path)?)?;
+        Ok(db)
+    }
+
+    /// Parse `route` and `route6` objects from an RPSL stream and add them to the database.
+    ///
+    /// Objects are separated by empty lines. All other object classes, comments (`%` and `#`)
+    /// and continuation lines are skipped. Returns the number of route objects added.
+    pub fn load_rpsl<R: Read>(&mut self, reader: R) -> Result<usize> {
+        let mut added = 0;
+        let mut prefix: Option<IpNet> = None;
+        let mut origin: Option<u32> = None;
+
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                if let (Some(p), Some(o)) = (prefix.take(), origin.take()) {
+                    self.insert(p, o);
+                    added += 1;
+                }
+                continue;
+            }
+            if line.starts_with('%')
+                || line.starts_with('#')
+                || line.starts_with(' ')
+                || line.starts_with('\t')
+                || line.starts_with('+')
+            {
+                continue;
+            }
+            let Some((attr, value)) = line.split_once(':') else {
+                continue;
+            };
+            match attr.trim().to_lowercase().as_str() {
+                "route" | "route6" => {
+                    let value = value.split('#').next().unwrap_or_default().trim();
+                    prefix = IpNet::from_str(value).ok();
+                }
+                "origin" => {
+                    origin = parse_origin(value);
+                }
+                _ => {}
+            }
+        }
+        if let (Some(p), Some(o)) = (prefix, origin) {
+            self.insert(p, o);
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    pub fn insert(&mut self, prefix: IpNet, origin: u32) {
+        self.routes.entry(prefix).or_default().insert(origin);
+    }
+
+    /// Origins registered for the exact prefix.
+    pub fn origins(&self, prefix: &IpNet) -> Option<&HashSet<u32>> {
+        self.routes.get(prefix)
+    }
+
+    /// Check an observed (prefix, origin) pair against the registered route objects.
+    ///
+    /// Only route objects for the exact prefix are considered; covering less-specific
+    /// objects do not make an observed more-specific announcement a match.
+    pub fn check(&self, prefix: &IpNet, origin: u32) -> IrrStatus {
+        match self.routes.get(prefix) {
+            None => IrrStatus::Missing,
+            Some(origins) if origins.contains(&origin) => IrrStatus::Match,
+            Some(_) => IrrStatus::Mismatch,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.values().map(|s| s.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+/// Processor for annotating pfx2as entries with their IRR route object status.
+pub struct IrrCheckProcessor<'a> {
+    irr: &'a IrrRouteDb,
+    entries: Vec<Pfx2AsIrrCount>,
+    origin_summary: BTreeMap<u32, (usize, usize, usize)>,
+}
+
+impl<'a> IrrCheckProcessor<'a> {
+    pub fn new(irr: &'a IrrRouteDb) -> Self {
+        Self {
+            irr,
+            entries: vec![],
+            origin_summary: BTreeMap::new(),
+        }
+    }
+
+    /// Check a single pfx2as entry. Entries with unparsable prefixes are skipped.
+    pub fn process_pfx2as(&mut self, pfx2as: &Prefix2AsCount) {
+        let prefix = match IpNet::from_str(pfx2as.prefix.as_str()) {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        let status = self.irr.check(&prefix, pfx2as.asn);
+        let mut irr_origins: Vec<u32> = self
+            .irr
+            .origins(&prefix)
+            .map(|s| s.iter().copied().collect())
+            .unwrap_or_default();
+        irr_origins.sort_unstable();
+
+        let (num_match, num_mismatch, num_missing) =
+            self.origin_summary.entry(pfx2as.asn).or_insert((0, 0, 0));
+        match status {
+            IrrStatus::Match => *num_match += 1,
+            IrrStatus::Mismatch => *num_mismatch += 1,
+            IrrStatus::Missing => *num_missing += 1,
+        }
+
+        self.entries.push(Pfx2AsIrrCount {
+            prefix: pfx2as.prefix.clone(),
+            asn: pfx2as.asn,
+            count: pfx2as.count,
+            status,
+            irr_origins,
+        });
+    }
+
+    pub fn into_irr_report(self) -> IrrReport {
+        let origins = self
+            .origin_summary
+            .into_iter()
+            .map(
+                |(asn, (num_match, num_mismatch, num_missing))| OriginIrrSummary {
+                    asn,
+                    num_match,
+                    num_mismatch,
+                    num_missing,
+                },
+            )
+            .collect();
+
+        IrrReport {
+            pfx2as: self.entries,
+            origins,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RPSL: &str = r#"% RIPE split file
+
+route:          1.1.1.0/24
+descr:          APNIC Research and Development
+                6 Cordelia St
+origin:         AS13335
+mnt-by:         MAINT-AS13335
+source:         RADB
+
+route6:         2606:4700::/32
+origin:         as13335 # cloudflare
+source:         RADB
+
+route:          8.8.8.0/24
+origin:         AS15169
+
+aut-num:        AS64496
+as-name:        EXAMPLE
+"#;
+
+    fn prefix_count(prefix: &str, asn: u32) -> Prefix2AsCount {
+        Prefix2AsCount {
+            prefix: prefix.to_string(),
+            asn,
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn test_load_rpsl() {
+        let mut db = IrrRouteDb::new();
+        assert_eq!(db.load_rpsl(RPSL.as_bytes()).unwrap(), 3);
+        assert_eq!(db.len(), 3);
+
+        let v6: IpNet = "2606:4700::/32".parse().unwrap();
+        assert!(db.origins(&v6).unwrap().contains(&13335));
+    }
+
+    #[test]
+    fn test_irr_check() {
+        let mut db = IrrRouteDb::new();
+        db.load_rpsl(RPSL.as_bytes()).unwrap();
+
+        let mut processor = IrrCheckProcessor::new(&db);
+        processor.process_pfx2as(&prefix_count("1.1.1.0/24", 13335));
+        processor.process_pfx2as(&prefix_count("8.8.8.0/24", 13335));
+        processor.process_pfx2as(&prefix_count("1.0.0.0/24", 13335));
+        processor.process_pfx2as(&prefix_count("not-a-prefix", 13335));
+        let report = processor.into_irr_report();
+
+        let statuses: Vec<IrrStatus> = report.pfx2as.iter().map(|e| e.status).collect();
+        assert_eq!(
+            statuses,
+            vec![IrrStatus::Match, IrrStatus::Mismatch, IrrStatus::Missing]
+        );
+        assert_eq!(report.pfx2as[1].irr_origins, vec![15169]);
+
+        assert_eq!(report.origins.len(), 1);
+        let summary = &report.origins[0];
+        assert_eq!(
+            (summary.num_match, summary.num_mismatch, summary.num_missing),
+            (1, 1, 1)
+        );
+    }
+}
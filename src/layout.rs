@@ -0,0 +1,434 @@
+use crate::format::is_data_extension;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Placeholder of a path template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    /// data type, e.g. `pfx2as` or `as2rel-v4`
+    Type,
+    /// data type folder, shared by the as2rel variants
+    TypeDir,
+    Collector,
+    Year,
+    Month,
+    Day,
+    /// `YYYY-MM-DD`
+    Date,
+    /// unix timestamp of the dump
+    Ts,
+    /// format and codec extension without leading dot
+    Ext,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "type" => Some(Field::Type),
+            "type_dir" => Some(Field::TypeDir),
+            "collector" => Some(Field::Collector),
+            "YYYY" => Some(Field::Year),
+            "MM" => Some(Field::Month),
+            "DD" => Some(Field::Day),
+            "date" => Some(Field::Date),
+            "ts" => Some(Field::Ts),
+            "ext" => Some(Field::Ext),
+            _ => None,
+        }
+    }
+
+    /// Whether a path segment is a valid value of the placeholder.
+    fn is_valid(&self, value: &str) -> bool {
+        let all_digits =
+            |len: usize| value.len() == len && value.chars().all(|c| c.is_ascii_digit());
+        match self {
+            Field::Type | Field::TypeDir => {
+                !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            }
+            Field::Collector => !value.is_empty() && !value.contains('/'),
+            Field::Year => all_digits(4),
+            Field::Month | Field::Day => all_digits(2),
+            Field::Date => {
+                value.len() == 10 && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+            }
+            Field::Ts => !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()),
+            Field::Ext => is_data_extension(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// Fields of a data file path parsed with a [PathLayout].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataFilePath {
+    pub data_type: String,
+    pub collector: String,
+    /// UTC date of the dump
+    pub date: NaiveDate,
+    /// unix timestamp of the dump
+    pub ts: i64,
+    pub extension: String,
+}
+
+/// Template of data file paths relative to the output directory, shared by bootstrap for writing
+/// and by the indexers for discovering data files.
+///
+/// Templates use the placeholders `{type}`, `{type_dir}` (the data type folder, shared by the
+/// as2rel variants), `{collector}`, `{YYYY}`, `{MM}`, `{DD}`, `{date}` (`YYYY-MM-DD`), `{ts}`
+/// (unix timestamp) and `{ext}`; `{type}`, `{collector}`, `{ts}` and `{ext}` are required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathLayout {
+    template: String,
+    tokens: Vec<Token>,
+}
+
+impl PathLayout {
+    /// Layout written by earlier versions, with the as2rel variants sharing the `as2rel` folder.
+    pub const DEFAULT: &'static str =
+        "{type_dir}/{collector}/{YYYY}/{MM}/{type}_{collector}_{date}_{ts}.{ext}";
+
+    /// Hive-style partitions, e.g. `pfx2as/date=2022-02-01/collector=rrc00/...`.
+    pub const HIVE: &'static str =
+        "{type_dir}/date={date}/collector={collector}/{type}_{collector}_{date}_{ts}.{ext}";
+
+    pub fn new(template: &str) -> Result<Self> {
+        if template.is_empty() || template.starts_with('/') {
+            return Err(anyhow!(
+                "path template {} must be relative to the output directory",
+                template
+            ));
+        }
+        let mut tokens = vec![];
+        let mut rest = template;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| anyhow!("unclosed placeholder in {}", template))?;
+                    let name = &rest[1..end];
+                    let field = Field::from_name(name).ok_or_else(|| {
+                        anyhow!("unknown placeholder {{{}}} in {}", name, template)
+                    })?;
+                    tokens.push(Token::Field(field));
+                    rest = &rest[end + 1..];
+                }
+                Some(start) => {
+                    tokens.push(Token::Literal(rest[..start].to_string()));
+                    rest = &rest[start..];
+                }
+                None => {
+                    tokens.push(Token::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+        if tokens
+            .iter()
+            .any(|t| matches!(t, Token::Literal(l) if l.contains('}')))
+        {
+            return Err(anyhow!("unopened placeholder in {}", template));
+        }
+        for (field, name) in [
+            (Field::Type, "type"),
+            (Field::Collector, "collector"),
+            (Field::Ts, "ts"),
+            (Field::Ext, "ext"),
+        ] {
+            if !tokens.contains(&Token::Field(field)) {
+                return Err(anyhow!(
+                    "path template {} is missing the {{{}}} placeholder",
+                    template,
+                    name
+                ));
+            }
+        }
+        Ok(PathLayout {
+            template: template.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        self.template.as_str()
+    }
+
+    /// Build the path of a data file under the output directory.
+    pub fn format(
+        &self,
+        output_dir: &str,
+        data_type: &str,
+        collector: &str,
+        ts: &NaiveDateTime,
+        extension: &str,
+    ) -> String {
+        let mut path = format!("{}/", output_dir.trim_end_matches('/'));
+        for token in &self.tokens {
+            match token {
+                Token::Literal(l) => path.push_str(l),
+                Token::Field(field) => path.push_str(
+                    match field {
+                        Field::Type => data_type.to_string(),
+                        Field::TypeDir => type_dir(data_type).to_string(),
+                        Field::Collector => collector.to_string(),
+                        Field::Year => ts.format("%Y").to_string(),
+                        Field::Month => ts.format("%m").to_string(),
+                        Field::Day => ts.format("%d").to_string(),
+                        Field::Date => ts.format("%Y-%m-%d").to_string(),
+                        Field::Ts => ts.and_utc().timestamp().to_string(),
+                        Field::Ext => extension.to_string(),
+                    }
+                    .as_str(),
+                ),
+            }
+        }
+        path
+    }
+
+    /// Parse a data file path, matching the template against its trailing path components.
+    /// Returns `None` if the path does not follow the layout.
+    pub fn parse(&self, path: &str) -> Option<DataFilePath> {
+        let depth = self.template.matches('/').count() + 1;
+        let components: Vec<&str> = path.split('/').collect();
+        if components.len() < depth {
+            return None;
+        }
+        let relative = components[components.len() - depth..].join("/");
+
+        let mut values = HashMap::new();
+        if !match_tokens(&self.tokens, relative.as_str(), &mut values) {
+            return None;
+        }
+        let data_type = values.get(&Field::Type)?.to_string();
+        let ts = values.get(&Field::Ts)?.parse::<i64>().ok()?;
+        let datetime = DateTime::from_timestamp(ts, 0)?.naive_utc();
+
+        // the type folder and all date placeholders have to agree with the type and timestamp
+        for (field, value) in &values {
+            let expected = match field {
+                Field::TypeDir => type_dir(data_type.as_str()).to_string(),
+                Field::Year => datetime.format("%Y").to_string(),
+                Field::Month => datetime.format("%m").to_string(),
+                Field::Day => datetime.format("%d").to_string(),
+                Field::Date => datetime.format("%Y-%m-%d").to_string(),
+                _ => continue,
+            };
+            if *value != expected {
+                return None;
+            }
+        }
+
+        Some(DataFilePath {
+            data_type,
+            collector: values.get(&Field::Collector)?.to_string(),
+            date: datetime.date(),
+            ts,
+            extension: values.get(&Field::Ext)?.to_string(),
+        })
+    }
+
+    /// Parse the data file paths following the layout, keeping a single file per data type,
+    /// collector and dump timestamp where a dump was written in several formats, e.g. after
+    /// switching `--format`. The most recently modified copy is kept. Files are returned ordered
+    /// by data type, collector and timestamp.
+    pub fn parse_unique<I>(&self, paths: I) -> Vec<(DataFilePath, String)>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        type Copy = (Option<SystemTime>, DataFilePath, String);
+        let mut files: BTreeMap<(String, String, i64), Copy> = BTreeMap::new();
+        for path in paths {
+            let Some(file) = self.parse(path.as_str()) else {
+                continue;
+            };
+            let modified = fs::metadata(path.as_str()).and_then(|m| m.modified()).ok();
+            let key = (file.data_type.clone(), file.collector.clone(), file.ts);
+            match files.get(&key) {
+                Some((kept_modified, _, kept_path))
+                    if (*kept_modified, kept_path) >= (modified, &path) => {}
+                _ => {
+                    files.insert(key, (modified, file, path));
+                }
+            }
+        }
+        files
+            .into_values()
+            .map(|(_, file, path)| (file, path))
+            .collect()
+    }
+}
+
+impl Default for PathLayout {
+    fn default() -> Self {
+        PathLayout::new(PathLayout::DEFAULT).unwrap()
+    }
+}
+
+impl Display for PathLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+impl FromStr for PathLayout {
+    type Err = String;
+
+    /// Parse `default`, `hive` or a custom template.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let template = match s {
+            "default" => PathLayout::DEFAULT,
+            "hive" => PathLayout::HIVE,
+            _ => s,
+        };
+        PathLayout::new(template).map_err(|e| e.to_string())
+    }
+}
+
+/// Folder of a data type, shared by the as2rel variants.
+fn type_dir(data_type: &str) -> &str {
+    match data_type.starts_with("as2rel") {
+        true => "as2rel",
+        false => data_type,
+    }
+}
+
+/// Match the tokens against a path, backtracking over the possible placeholder values so that
+/// values may contain the separators around them, e.g. collectors with underscores.
+fn match_tokens<'a>(tokens: &[Token], path: &'a str, values: &mut HashMap<Field, &'a str>) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(t) => t,
+        None => return path.is_empty(),
+    };
+    match token {
+        Token::Literal(l) => match path.strip_prefix(l.as_str()) {
+            Some(p) => match_tokens(rest, p, values),
+            None => false,
+        },
+        Token::Field(field) => {
+            if let Some(value) = values.get(field) {
+                return match path.strip_prefix(*value) {
+                    Some(p) => match_tokens(rest, p, values),
+                    None => false,
+                };
+            }
+            for (end, _) in path.char_indices().skip(1).chain([(path.len(), ' ')]) {
+                let value = &path[..end];
+                if value.contains('/') {
+                    break;
+                }
+                if !field.is_valid(value) {
+                    continue;
+                }
+                values.insert(*field, value);
+                if match_tokens(rest, &path[end..], values) {
+                    return true;
+                }
+                values.remove(field);
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_parse() {
+        let ts = DateTime::from_timestamp(1643673600, 0).unwrap().naive_utc();
+        let layout = PathLayout::default();
+        let path = layout.format("/data/", "as2rel-v4", "rrc16", &ts, "bz2");
+        assert_eq!(
+            path,
+            "/data/as2rel/rrc16/2022/02/as2rel-v4_rrc16_2022-02-01_1643673600.bz2"
+        );
+        let parsed = layout.parse(path.as_str()).unwrap();
+        assert_eq!(parsed.data_type, "as2rel-v4");
+        assert_eq!(parsed.collector, "rrc16");
+        assert_eq!(parsed.date, NaiveDate::from_ymd_opt(2022, 2, 1).unwrap());
+        assert_eq!(parsed.ts, 1643673600);
+
+        // collector names with underscores
+        let path = layout.format("data", "pfx2as", "route-views_sg", &ts, "ndjson.zst");
+        let parsed = layout.parse(path.as_str()).unwrap();
+        assert_eq!(parsed.collector, "route-views_sg");
+        assert_eq!(parsed.extension, "ndjson.zst");
+
+        let hive: PathLayout = "hive".parse().unwrap();
+        let path = hive.format("data", "peer-stats", "rrc00", &ts, "parquet");
+        assert_eq!(
+            path,
+            "data/peer-stats/date=2022-02-01/collector=rrc00/peer-stats_rrc00_2022-02-01_1643673600.parquet"
+        );
+        assert_eq!(hive.parse(path.as_str()).unwrap().collector, "rrc00");
+        assert!(layout.parse(path.as_str()).is_none());
+
+        assert!(layout
+            .parse("data/pfx2as/rrc00/2022/02/pfx2as_rrc00_2022-02-01_1643673600.bz2.tmp")
+            .is_none());
+        assert!(layout
+            .parse("data/pfx2as/rrc00/2022/03/pfx2as_rrc00_2022-02-01_1643673600.bz2")
+            .is_none());
+        assert!(layout
+            .parse("pfx2as_rrc00_2022-02-01_1643673600.bz2")
+            .is_none());
+
+        assert!(PathLayout::new("{type}/{collector}/{ts}").is_err());
+        assert!(PathLayout::new("{type}/{region}/{collector}_{ts}.{ext}").is_err());
+        let flat = PathLayout::new("{collector}.{type}.{ts}.{ext}").unwrap();
+        let parsed = flat.parse("x/rrc00.pfx2as.1643673600.gz").unwrap();
+        assert_eq!(parsed.data_type, "pfx2as");
+    }
+
+    #[test]
+    fn test_parse_unique() {
+        let dir = std::env::temp_dir().join(format!("parse-unique-test-{}", std::process::id()));
+        let layout = PathLayout::default();
+        let output_dir = dir.to_str().unwrap();
+        let ts = |t: i64| DateTime::from_timestamp(t, 0).unwrap().naive_utc();
+        let now = SystemTime::now();
+        let mut paths = vec![];
+        for (collector, t, ext, age) in [
+            ("rrc00", 1643673600, "bz2", 60),
+            ("rrc00", 1643673600, "parquet", 0),
+            ("rrc00", 1643702400, "bz2", 60),
+            ("rrc01", 1643673600, "ndjson.zst", 60),
+        ] {
+            let path = layout.format(output_dir, "pfx2as", collector, &ts(t), ext);
+            fs::create_dir_all(std::path::Path::new(path.as_str()).parent().unwrap()).unwrap();
+            fs::File::create(path.as_str())
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+            paths.push(path);
+        }
+        paths.push(format!("{}/README.md", output_dir));
+
+        let files: Vec<(String, i64, String)> = layout
+            .parse_unique(paths)
+            .into_iter()
+            .map(|(file, _)| (file.collector, file.ts, file.extension))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("rrc00".to_string(), 1643673600, "parquet".to_string()),
+                ("rrc00".to_string(), 1643702400, "bz2".to_string()),
+                ("rrc01".to_string(), 1643673600, "ndjson.zst".to_string()),
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
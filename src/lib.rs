@@ -1,23 +1,289 @@
+pub mod archive;
 pub mod as2rel;
+pub mod atomic;
+pub mod codec;
+pub mod config;
+pub mod format;
+pub mod irr;
+pub mod layout;
+pub mod manifest;
 pub mod peer_stats;
 pub mod pfx2as;
+pub mod retry;
+pub mod rib_state;
+pub mod scheduler;
+pub mod selection;
+pub mod summary;
+pub mod timerange;
+pub mod updates;
+pub mod watch;
 
 // Re-export tier-1 constants from as2rel
 pub use as2rel::{CANDIDATE_TIER1_V4, CANDIDATE_TIER1_V6, TRUE_TIER1};
 
 // Re-export types from their respective modules
-pub use as2rel::{As2Rel, As2RelCount};
+pub use archive::{CollectorFilter, DumpItem, ProjectMapping};
+pub use as2rel::{As2Rel, As2RelCount, Tier1Config};
+pub use codec::Codec;
+pub use format::{OutputFormat, OutputOptions};
+pub use irr::{IrrReport, IrrRouteDb, IrrStatus, OriginIrrSummary, Pfx2AsIrrCount};
+pub use layout::{DataFilePath, PathLayout};
 pub use peer_stats::{PeerInfo, RibPeerInfo};
-pub use pfx2as::{Prefix2As, Prefix2AsCount};
+pub use pfx2as::{OriginStats, Prefix2As, Prefix2AsCount};
+pub use rib_state::{RibEntry, RibState};
+pub use selection::SelectionPolicy;
+pub use updates::{
+    AsLinkCount, PrefixChurnCount, UpdatesBaseline, UpdatesNew, UpdatesPeerCount, UpdatesStats,
+};
 
 // Re-export processors
 pub use as2rel::{dedup_path, As2RelProcessor};
+pub use irr::IrrCheckProcessor;
 pub use peer_stats::PeerStatsProcessor;
-pub use pfx2as::Pfx2AsProcessor;
+pub use pfx2as::{OriginStatsProcessor, Pfx2AsProcessor};
+pub use updates::UpdatesProcessor;
 
 use anyhow::Result;
+use bgpkit_parser::models::ElemType;
 use bgpkit_parser::BgpkitParser;
 use ipnet::IpNet;
+use retry::Cancellation;
+use std::net::IpAddr;
+use tracing::info;
+
+/// A data file type produced from a RIB dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RibDataType {
+    PeerStats,
+    Pfx2As,
+    As2Rel,
+    As2RelV4,
+    As2RelV6,
+}
+
+impl RibDataType {
+    pub const ALL: [RibDataType; 5] = [
+        RibDataType::PeerStats,
+        RibDataType::Pfx2As,
+        RibDataType::As2Rel,
+        RibDataType::As2RelV4,
+        RibDataType::As2RelV6,
+    ];
+
+    /// Name of the data type as used in output file names.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RibDataType::PeerStats => "peer-stats",
+            RibDataType::Pfx2As => "pfx2as",
+            RibDataType::As2Rel => "as2rel",
+            RibDataType::As2RelV4 => "as2rel-v4",
+            RibDataType::As2RelV6 => "as2rel-v6",
+        }
+    }
+
+    fn is_as2rel(&self) -> bool {
+        matches!(
+            self,
+            RibDataType::As2Rel | RibDataType::As2RelV4 | RibDataType::As2RelV6
+        )
+    }
+}
+
+impl std::fmt::Display for RibDataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for RibDataType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        RibDataType::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown data type {}, expected one of peer-stats, pfx2as, as2rel, as2rel-v4, as2rel-v6",
+                    s
+                )
+            })
+    }
+}
+
+/// Data types to produce from a RIB dump and the tier-1 ASes used for as2rel inference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RibOptions {
+    pub types: Vec<RibDataType>,
+    pub tier1: Tier1Config,
+}
+
+impl Default for RibOptions {
+    fn default() -> Self {
+        RibOptions::new(&RibDataType::ALL)
+    }
+}
+
+impl RibOptions {
+    /// Options producing the given data types with the default tier-1 ASes.
+    pub fn new(types: &[RibDataType]) -> Self {
+        RibOptions {
+            types: types.to_vec(),
+            tier1: Tier1Config::default(),
+        }
+    }
+}
+
+/// Results of the processors selected for a RIB dump, `None` for the data types not requested.
+#[derive(Debug, Default)]
+pub struct RibResults {
+    pub peer_stats: Option<RibPeerInfo>,
+    pub pfx2as: Option<Prefix2As>,
+    pub as2rel: Option<As2Rel>,
+    pub as2rel_v4: Option<As2Rel>,
+    pub as2rel_v6: Option<As2Rel>,
+    /// number of routes processed
+    pub elems_count: u64,
+}
+
+impl RibResults {
+    /// Whether the result of a data type was computed.
+    pub fn contains(&self, data_type: RibDataType) -> bool {
+        match data_type {
+            RibDataType::PeerStats => self.peer_stats.is_some(),
+            RibDataType::Pfx2As => self.pfx2as.is_some(),
+            RibDataType::As2Rel => self.as2rel.is_some(),
+            RibDataType::As2RelV4 => self.as2rel_v4.is_some(),
+            RibDataType::As2RelV6 => self.as2rel_v6.is_some(),
+        }
+    }
+
+    /// Write the result of a data type in the given format, failing if it was not computed.
+    ///
+    /// `date` (`YYYY-MM-DD`) is only written to NDJSON records.
+    pub fn write_data<W: std::io::Write + Send>(
+        &self,
+        data_type: RibDataType,
+        options: &OutputOptions,
+        date: &str,
+        writer: W,
+    ) -> Result<W> {
+        let missing = || anyhow::anyhow!("no {} results to write", data_type);
+        match data_type {
+            RibDataType::PeerStats => {
+                let data = self.peer_stats.as_ref().ok_or_else(missing)?;
+                format::write_data(data, options, date, writer)
+            }
+            RibDataType::Pfx2As => {
+                let data = self.pfx2as.as_ref().ok_or_else(missing)?;
+                format::write_data(data, options, date, writer)
+            }
+            RibDataType::As2Rel => {
+                let data = self.as2rel.as_ref().ok_or_else(missing)?;
+                format::write_data(data, options, date, writer)
+            }
+            RibDataType::As2RelV4 => {
+                let data = self.as2rel_v4.as_ref().ok_or_else(missing)?;
+                format::write_data(data, options, date, writer)
+            }
+            RibDataType::As2RelV6 => {
+                let data = self.as2rel_v6.as_ref().ok_or_else(missing)?;
+                format::write_data(data, options, date, writer)
+            }
+        }
+    }
+}
+
+/// The peer-stats, pfx2as and as2rel processors fed with the same RIB routes.
+///
+/// Processors of data types that are not requested are left out.
+struct RibProcessors {
+    peer_stats: Option<PeerStatsProcessor>,
+    pfx2as: Option<Pfx2AsProcessor>,
+    as2rel: Option<As2RelProcessor>,
+    types: Vec<RibDataType>,
+    elems_count: u64,
+}
+
+impl RibProcessors {
+    fn new(options: &RibOptions) -> Self {
+        let types = &options.types;
+        Self {
+            peer_stats: types
+                .contains(&RibDataType::PeerStats)
+                .then(PeerStatsProcessor::new),
+            pfx2as: types
+                .contains(&RibDataType::Pfx2As)
+                .then(Pfx2AsProcessor::new),
+            as2rel: types
+                .iter()
+                .any(|t| t.is_as2rel())
+                .then(|| As2RelProcessor::with_tier1(options.tier1.clone())),
+            types: types.to_vec(),
+            elems_count: 0,
+        }
+    }
+
+    /// Process a single RIB route. AS path must be from collector ([0]) to origin ([last]).
+    fn process_route(
+        &mut self,
+        peer_ip: IpAddr,
+        peer_asn: u32,
+        prefix: IpNet,
+        as_path: Option<&[u32]>,
+    ) {
+        self.elems_count += 1;
+
+        // Extract prefix info
+        let (prefix_v4, prefix_v6) = match prefix {
+            IpNet::V4(net) => (Some(net), None),
+            IpNet::V6(net) => (None, Some(net)),
+        };
+
+        // Process AS path data
+        let mut connected_asn = None;
+        if let Some(u32_path) = as_path {
+            // Get connected ASN (second hop in path)
+            connected_asn = u32_path.get(1).copied();
+
+            // Get origin ASN for pfx2as
+            if let (Some(pfx2as), Some(origin_asn)) = (self.pfx2as.as_mut(), u32_path.last()) {
+                pfx2as.record(prefix.to_string(), *origin_asn);
+            }
+
+            // Process AS relationships
+            if let Some(as2rel) = self.as2rel.as_mut() {
+                as2rel.process_path(peer_ip, prefix, u32_path);
+            }
+        }
+
+        // Update peer stats
+        if let Some(peer_stats) = self.peer_stats.as_mut() {
+            peer_stats.process_element(peer_ip, peer_asn, prefix_v4, prefix_v6, connected_asn);
+        }
+    }
+
+    fn into_results(self, project: &str, collector: &str, rib_dump_url: &str) -> RibResults {
+        let mut results = RibResults {
+            peer_stats: self
+                .peer_stats
+                .map(|p| p.into_peer_info(project, collector, rib_dump_url)),
+            pfx2as: self
+                .pfx2as
+                .map(|p| p.into_prefix2as(project, collector, rib_dump_url)),
+            elems_count: self.elems_count,
+            ..Default::default()
+        };
+        if let Some(as2rel) = self.as2rel {
+            let (global, v4, v6) = as2rel.into_as2rel_triple(project, collector, rib_dump_url);
+            let types = &self.types;
+            results.as2rel = types.contains(&RibDataType::As2Rel).then_some(global);
+            results.as2rel_v4 = types.contains(&RibDataType::As2RelV4).then_some(v4);
+            results.as2rel_v6 = types.contains(&RibDataType::As2RelV6).then_some(v6);
+        }
+        results
+    }
+}
 
 /// collect information from a provided RIB file
 ///
@@ -34,59 +300,157 @@ pub fn parse_rib_file(
     project: &str,
     collector: &str,
 ) -> Result<(RibPeerInfo, Prefix2As, (As2Rel, As2Rel, As2Rel))> {
-    let mut peer_stats_collector = PeerStatsProcessor::new();
-    let mut pfx2as_collector = Pfx2AsProcessor::new();
-    let mut as2rel_collector = As2RelProcessor::new();
+    let results = parse_rib_file_types(
+        file_url,
+        project,
+        collector,
+        &RibOptions::default(),
+        &Cancellation::new(),
+    )?;
+    Ok((
+        results.peer_stats.unwrap(),
+        results.pfx2as.unwrap(),
+        (
+            results.as2rel.unwrap(),
+            results.as2rel_v4.unwrap(),
+            results.as2rel_v6.unwrap(),
+        ),
+    ))
+}
+
+/// collect the given data types from a provided RIB file, running only the processors needed
+///
+/// Parsing stops with an error once `cancellation` is set.
+pub fn parse_rib_file_types(
+    file_url: &str,
+    project: &str,
+    collector: &str,
+    options: &RibOptions,
+    cancellation: &Cancellation,
+) -> Result<RibResults> {
+    let mut processors = RibProcessors::new(options);
 
     for elem in BgpkitParser::new(file_url)? {
-        let peer_ip = elem.peer_ip;
-        let peer_asn = elem.peer_asn.to_u32();
-        let prefix = elem.prefix.prefix;
+        cancellation.check()?;
+        let as_path = elem.as_path.as_ref().and_then(|p| p.to_u32_vec_opt(true));
+        processors.process_route(
+            elem.peer_ip,
+            elem.peer_asn.to_u32(),
+            elem.prefix.prefix,
+            as_path.as_deref(),
+        );
+    }
 
-        // Extract prefix info
-        let (prefix_v4, prefix_v6) = match prefix {
-            IpNet::V4(net) => (Some(net), None),
-            IpNet::V6(net) => (None, Some(net)),
-        };
+    Ok(processors.into_results(project, collector, file_url))
+}
 
-        // Process AS path data
-        let mut connected_asn = None;
-        if let Some(as_path) = elem.as_path.clone() {
-            if let Some(u32_path) = as_path.to_u32_vec_opt(true) {
-                // Get connected ASN (second hop in path)
-                connected_asn = u32_path.get(1).copied();
-
-                // Get origin ASN for pfx2as
-                if let Some(origin_asn) = u32_path.last().copied() {
-                    pfx2as_collector.record(prefix.to_string(), origin_asn);
-                }
+/// reconstruct the RIB state at `target_ts` and collect information from it
+///
+/// The RIB file is loaded into a [RibState] and the UPDATES files are replayed in the given
+/// order, applying all messages with timestamps up to `target_ts` (unix timestamp). Messages
+/// before the RIB dump time `rib_ts` are skipped. The synthesized table is then processed the
+/// same way as [parse_rib_file_types] does with the given options, and stops once `cancellation`
+/// is set.
+#[allow(clippy::too_many_arguments)]
+pub fn parse_rib_with_updates(
+    rib_url: &str,
+    rib_ts: i64,
+    updates_urls: &[String],
+    target_ts: i64,
+    project: &str,
+    collector: &str,
+    options: &RibOptions,
+    cancellation: &Cancellation,
+) -> Result<RibResults> {
+    let mut rib_state = RibState::new();
+
+    for elem in BgpkitParser::new(rib_url)? {
+        cancellation.check()?;
+        let as_path = elem.as_path.as_ref().and_then(|p| p.to_u32_vec_opt(true));
+        rib_state.apply_announcement(
+            elem.peer_ip,
+            elem.peer_asn.to_u32(),
+            elem.prefix.prefix,
+            as_path,
+        );
+    }
+    info!("loaded {} routes from {}", rib_state.len(), rib_url);
 
-                // Process AS relationships
-                as2rel_collector.process_path(peer_ip, prefix, &u32_path);
+    for updates_url in updates_urls {
+        for elem in BgpkitParser::new(updates_url.as_str())? {
+            cancellation.check()?;
+            let elem_ts = elem.timestamp as i64;
+            if elem_ts < rib_ts || elem_ts > target_ts {
+                continue;
+            }
+            match elem.elem_type {
+                ElemType::ANNOUNCE => {
+                    let as_path = elem.as_path.as_ref().and_then(|p| p.to_u32_vec_opt(true));
+                    rib_state.apply_announcement(
+                        elem.peer_ip,
+                        elem.peer_asn.to_u32(),
+                        elem.prefix.prefix,
+                        as_path,
+                    );
+                }
+                ElemType::WITHDRAW => {
+                    rib_state.apply_withdrawal(elem.peer_ip, elem.prefix.prefix);
+                }
             }
         }
+    }
+    info!(
+        "reconstructed {} routes at {} from {} UPDATES files",
+        rib_state.len(),
+        target_ts,
+        updates_urls.len()
+    );
 
-        // Update peer stats
-        peer_stats_collector.process_element(
-            peer_ip,
-            peer_asn,
-            prefix_v4,
-            prefix_v6,
-            connected_asn,
-        );
+    let mut processors = RibProcessors::new(options);
+    for (peer_ip, prefix, entry) in rib_state.iter() {
+        processors.process_route(peer_ip, entry.peer_asn, prefix, entry.as_path.as_deref());
     }
 
-    let peer_info = peer_stats_collector.into_peer_info(project, collector, file_url);
-    let pfx2as = pfx2as_collector.into_prefix2as(project, collector, file_url);
-    let as2rel_triple = as2rel_collector.into_as2rel_triple(project, collector, file_url);
+    Ok(processors.into_results(project, collector, rib_url))
+}
+
+/// collect announcement and withdrawal statistics from a time window of UPDATES files
+///
+/// `ts_start` and `ts_end` (unix timestamps) describe the window covered by the files and are
+/// only recorded in the output. Pairs and links in `baseline` are not reported as new. Parsing
+/// stops once `cancellation` is set.
+pub fn parse_updates_files(
+    file_urls: &[String],
+    project: &str,
+    collector: &str,
+    ts_start: i64,
+    ts_end: i64,
+    baseline: UpdatesBaseline,
+    cancellation: &Cancellation,
+) -> Result<(UpdatesStats, UpdatesNew)> {
+    let mut updates_collector = UpdatesProcessor::with_baseline(baseline);
+
+    for file_url in file_urls {
+        for elem in BgpkitParser::new(file_url.as_str())? {
+            cancellation.check()?;
+            let as_path = elem.as_path.as_ref().and_then(|p| p.to_u32_vec_opt(true));
+            updates_collector.process_element(
+                elem.peer_ip,
+                elem.peer_asn.to_u32(),
+                elem.prefix.prefix,
+                elem.elem_type == ElemType::ANNOUNCE,
+                as_path.as_deref(),
+            );
+        }
+    }
 
-    Ok((peer_info, pfx2as, as2rel_triple))
+    Ok(updates_collector.into_updates(project, collector, ts_start, ts_end, file_urls))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::as2rel::dedup_path;
-    use crate::parse_rib_file;
+    use crate::{parse_rib_file, OutputOptions, RibDataType, RibOptions, RibProcessors};
     use serde_json::json;
     use std::fs::File;
     use tracing::{info, Level};
@@ -133,4 +497,34 @@ mod tests {
             vec![0, 1, 2, 3, 4]
         );
     }
+
+    #[test]
+    fn test_selected_processors() {
+        let types: Vec<RibDataType> = "pfx2as,as2rel-v6"
+            .split(',')
+            .map(|t| t.parse().unwrap())
+            .collect();
+        assert!("as2rel-v5".parse::<RibDataType>().is_err());
+
+        let mut processors = RibProcessors::new(&RibOptions::new(&types));
+        processors.process_route(
+            "10.0.0.1".parse().unwrap(),
+            65001,
+            "192.0.2.0/24".parse().unwrap(),
+            Some(&[65001, 65002, 65003]),
+        );
+        let results = processors.into_results("riperis", "rrc00", "rib.gz");
+        assert!(results.peer_stats.is_none());
+        assert!(results.pfx2as.is_some());
+        assert!(results.as2rel.is_none() && results.as2rel_v4.is_none());
+        assert!(results.contains(RibDataType::As2RelV6));
+        assert!(results
+            .write_data(
+                RibDataType::PeerStats,
+                &OutputOptions::default(),
+                "2022-02-01",
+                vec![]
+            )
+            .is_err());
+    }
 }
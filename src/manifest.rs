@@ -0,0 +1,209 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Processing status of a dump file in a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    /// processing started, but never finished, e.g. because the run crashed
+    Started,
+    Succeeded,
+    Failed,
+    /// all output files already existed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestOutput {
+    pub path: String,
+    pub sha256: String,
+}
+
+/// A single status change of a dump file, stored as one line in the manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRecord {
+    /// URL or path of the input dump file, also identifying the item
+    pub url: String,
+    pub collector: String,
+    /// dump time of the input file (unix timestamp)
+    pub timestamp: i64,
+    pub status: ItemStatus,
+    /// processing start time (unix timestamp)
+    pub started_at: i64,
+    /// processing end time (unix timestamp), absent while running
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+    pub outputs: Vec<ManifestOutput>,
+}
+
+/// Append-only JSON lines manifest of a bootstrap output directory.
+///
+/// Every status change of an item is appended as a new line, the latest line of an item being
+/// its current status. Appends are serialized so the manifest can be shared across threads.
+pub struct RunManifest {
+    path: PathBuf,
+    writer: Mutex<File>,
+}
+
+impl RunManifest {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        // terminate a partial line left by a crash so that new records start on their own line
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last_byte = [0u8; 1];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last_byte)?;
+            if last_byte[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &ManifestRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(line.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read the latest record of every item. A missing manifest yields no records, and a
+    /// trailing partial line left by a crash is ignored.
+    pub fn load_latest(path: &Path) -> Result<HashMap<String, ManifestRecord>> {
+        let mut latest = HashMap::new();
+        if !path.exists() {
+            return Ok(latest);
+        }
+        for line in BufReader::new(File::open(path)?).lines() {
+            let record: ManifestRecord = match serde_json::from_str(line?.as_str()) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            latest.insert(record.url.clone(), record);
+        }
+        Ok(latest)
+    }
+}
+
+/// Compute the hex-encoded SHA-256 checksum of a file.
+pub fn file_sha256(path: &str) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Summary of the latest item statuses of a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestReport {
+    pub total_items: usize,
+    pub status_counts: BTreeMap<ItemStatus, usize>,
+    /// (url, error) of all failed items
+    pub failed: Vec<(String, String)>,
+    /// urls of items that started but never finished
+    pub incomplete: Vec<String>,
+}
+
+impl ManifestReport {
+    pub fn from_records(latest: &HashMap<String, ManifestRecord>) -> Self {
+        let mut status_counts = BTreeMap::new();
+        let mut failed = vec![];
+        let mut incomplete = vec![];
+        for record in latest.values() {
+            *status_counts.entry(record.status).or_insert(0) += 1;
+            match record.status {
+                ItemStatus::Failed => {
+                    failed.push((record.url.clone(), record.error.clone().unwrap_or_default()))
+                }
+                ItemStatus::Started => incomplete.push(record.url.clone()),
+                _ => {}
+            }
+        }
+        failed.sort();
+        incomplete.sort();
+        ManifestReport {
+            total_items: latest.len(),
+            status_counts,
+            failed,
+            incomplete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(url: &str, status: ItemStatus) -> ManifestRecord {
+        ManifestRecord {
+            url: url.to_string(),
+            collector: "rrc00".to_string(),
+            timestamp: 1643673600,
+            status,
+            started_at: 1700000000,
+            finished_at: None,
+            error: None,
+            outputs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_manifest_latest_status() {
+        let path = std::env::temp_dir().join(format!("manifest-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let manifest = RunManifest::open(&path).unwrap();
+        manifest.append(&record("a", ItemStatus::Started)).unwrap();
+        manifest.append(&record("b", ItemStatus::Started)).unwrap();
+        manifest
+            .append(&record("a", ItemStatus::Succeeded))
+            .unwrap();
+        manifest.append(&record("c", ItemStatus::Failed)).unwrap();
+        drop(manifest);
+        // partial line of a crashed run
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"url\":\"d\",")
+            .unwrap();
+
+        let manifest = RunManifest::open(&path).unwrap();
+        manifest.append(&record("c", ItemStatus::Failed)).unwrap();
+        drop(manifest);
+
+        let latest = RunManifest::load_latest(&path).unwrap();
+        assert_eq!(latest.len(), 3);
+        assert_eq!(latest["a"].status, ItemStatus::Succeeded);
+
+        let report = ManifestReport::from_records(&latest);
+        assert_eq!(report.status_counts[&ItemStatus::Started], 1);
+        assert_eq!(report.incomplete, vec!["b".to_string()]);
+        assert_eq!(report.failed.len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
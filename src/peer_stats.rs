@@ -1,9 +1,9 @@
 use ipnet::{Ipv4Net, Ipv6Net};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RibPeerInfo {
     pub project: String,
     pub collector: String,
@@ -11,7 +11,7 @@ pub struct RibPeerInfo {
     pub peers: HashMap<IpAddr, PeerInfo>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub ip: IpAddr,
     pub asn: u32,
@@ -1,5 +1,7 @@
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Prefix2As {
@@ -54,3 +56,155 @@ impl Default for Pfx2AsProcessor {
         Self::new()
     }
 }
+
+/// Per-origin announcement statistics derived from one or more pfx2as files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OriginStats {
+    pub asn: u32,
+    pub num_v4_pfxs: usize,
+    pub num_v6_pfxs: usize,
+    /// number of IPv4 addresses covered by the announced prefixes, overlaps counted once
+    pub v4_space: u64,
+    /// number of IPv6 /48s covered by the announced prefixes, overlaps counted once
+    pub v6_space_48s: u64,
+    /// number of collectors observing the origin
+    pub num_collectors: usize,
+    /// total number of RIB entries observed for the origin across all collectors
+    pub num_observations: usize,
+}
+
+#[derive(Default)]
+struct OriginAccumulator {
+    v4_pfxs: HashSet<Ipv4Net>,
+    v6_pfxs: HashSet<Ipv6Net>,
+    collectors: HashSet<String>,
+    observations: usize,
+}
+
+/// Processor for computing per-origin statistics from pfx2as data.
+pub struct OriginStatsProcessor {
+    origin_map: BTreeMap<u32, OriginAccumulator>,
+}
+
+impl OriginStatsProcessor {
+    pub fn new() -> Self {
+        Self {
+            origin_map: BTreeMap::new(),
+        }
+    }
+
+    /// Add all entries of a pfx2as file. Entries with unparsable prefixes are skipped.
+    pub fn process_pfx2as(&mut self, pfx2as: &Prefix2As) {
+        for entry in &pfx2as.pfx2as {
+            self.process_entry(pfx2as.collector.as_str(), entry);
+        }
+    }
+
+    /// Add a single entry seen by the given collector. Unparsable prefixes are skipped.
+    pub fn process_entry(&mut self, collector: &str, entry: &Prefix2AsCount) {
+        let prefix = match IpNet::from_str(entry.prefix.as_str()) {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        let acc = self.origin_map.entry(entry.asn).or_default();
+        match prefix {
+            IpNet::V4(net) => {
+                acc.v4_pfxs.insert(net);
+            }
+            IpNet::V6(net) => {
+                acc.v6_pfxs.insert(net);
+            }
+        }
+        if !acc.collectors.contains(collector) {
+            acc.collectors.insert(collector.to_string());
+        }
+        acc.observations += entry.count;
+    }
+
+    pub fn into_origin_stats(self) -> Vec<OriginStats> {
+        self.origin_map
+            .into_iter()
+            .map(|(asn, acc)| {
+                let v4_pfxs: Vec<Ipv4Net> = acc.v4_pfxs.into_iter().collect();
+                let v6_pfxs: Vec<Ipv6Net> = acc.v6_pfxs.into_iter().collect();
+                let v4_space = Ipv4Net::aggregate(&v4_pfxs)
+                    .iter()
+                    .map(|net| 1u64 << (32 - net.prefix_len()))
+                    .sum();
+                let v6_space_48s = Ipv6Net::aggregate(&v6_pfxs)
+                    .iter()
+                    .filter(|net| net.prefix_len() <= 48)
+                    .map(|net| 1u64 << (48 - net.prefix_len()))
+                    .sum();
+                OriginStats {
+                    asn,
+                    num_v4_pfxs: v4_pfxs.len(),
+                    num_v6_pfxs: v6_pfxs.len(),
+                    v4_space,
+                    v6_space_48s,
+                    num_collectors: acc.collectors.len(),
+                    num_observations: acc.observations,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for OriginStatsProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pfx2as(collector: &str, entries: &[(&str, u32, usize)]) -> Prefix2As {
+        Prefix2As {
+            project: "route-views".to_string(),
+            collector: collector.to_string(),
+            rib_dump_url: "".to_string(),
+            pfx2as: entries
+                .iter()
+                .map(|(prefix, asn, count)| Prefix2AsCount {
+                    prefix: prefix.to_string(),
+                    asn: *asn,
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_origin_stats() {
+        let mut processor = OriginStatsProcessor::new();
+        processor.process_pfx2as(&pfx2as(
+            "route-views2",
+            &[
+                ("1.1.1.0/24", 13335, 10),
+                ("1.0.0.0/24", 13335, 10),
+                ("1.1.1.0/25", 13335, 2),
+                ("2606:4700::/32", 13335, 5),
+            ],
+        ));
+        processor.process_pfx2as(&pfx2as(
+            "rrc00",
+            &[("1.1.1.0/24", 13335, 20), ("8.8.8.0/24", 15169, 20)],
+        ));
+        let stats = processor.into_origin_stats();
+        assert_eq!(stats.len(), 2);
+
+        let cloudflare = &stats[0];
+        assert_eq!(cloudflare.asn, 13335);
+        assert_eq!(cloudflare.num_v4_pfxs, 3);
+        assert_eq!(cloudflare.num_v6_pfxs, 1);
+        // the /25 is covered by the /24 and must not be counted twice
+        assert_eq!(cloudflare.v4_space, 512);
+        assert_eq!(cloudflare.v6_space_48s, 1 << 16);
+        assert_eq!(cloudflare.num_collectors, 2);
+        assert_eq!(cloudflare.num_observations, 47);
+
+        assert_eq!(stats[1].num_collectors, 1);
+    }
+}
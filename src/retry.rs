@@ -0,0 +1,357 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// Coarse classification of a processing failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    /// download or connection problem
+    Network,
+    /// the MRT file ended unexpectedly, e.g. a partially downloaded or mirrored file
+    Truncated,
+    /// the processing exceeded its wall-clock timeout
+    Timeout,
+    /// the file could not be parsed
+    Parse,
+    /// the results could not be written
+    Write,
+}
+
+impl FailureKind {
+    /// Classify an error by walking its chain of causes.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<TimeoutError>() {
+                return FailureKind::Timeout;
+            }
+            if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+                match io_error.kind() {
+                    std::io::ErrorKind::UnexpectedEof => return FailureKind::Truncated,
+                    std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut => return FailureKind::Network,
+                    _ => {}
+                }
+            }
+        }
+
+        // errors of the remote reader and MRT parser are mostly only distinguishable by message;
+        // URLs are left out, as the remote reader includes them in all of its errors
+        let message = format!("{:#}", error)
+            .to_lowercase()
+            .split_whitespace()
+            .filter(|word| !word.contains("://"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if ["unexpected eof", "truncated", "end of file"]
+            .iter()
+            .any(|m| message.contains(m))
+        {
+            return FailureKind::Truncated;
+        }
+        if [
+            "connection reset",
+            "connection refused",
+            "connection closed",
+            "connection aborted",
+            "broken pipe",
+            "timed out",
+            "dns error",
+            "error sending request",
+            "network is unreachable",
+        ]
+        .iter()
+        .any(|m| message.contains(m))
+        {
+            return FailureKind::Network;
+        }
+        FailureKind::Parse
+    }
+
+    /// Whether a failure of this kind may go away when retrying.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            FailureKind::Network | FailureKind::Truncated | FailureKind::Timeout
+        )
+    }
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FailureKind::Network => "network",
+            FailureKind::Truncated => "truncated",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Parse => "parse",
+            FailureKind::Write => "write",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Error returned when a task exceeds its wall-clock timeout.
+#[derive(Debug)]
+pub struct TimeoutError(pub Duration);
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {} seconds", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Flag telling a running task to stop early, set when the task exceeds its timeout.
+///
+/// Long-running tasks check it regularly, e.g. for every parsed route, and give up once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail if the task was cancelled.
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(anyhow!("task cancelled")),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Retry attempts with exponential backoff for transient failures.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// wait time before the first retry, doubled for every further retry
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// wall-clock timeout of each attempt
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(600),
+            timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait time before the given retry (1 for the first retry).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Run the task until it succeeds, fails with a non-transient error, or runs out of attempts.
+    ///
+    /// Each attempt is subject to the timeout, see [run_with_timeout]. The final error is returned
+    /// with its failure kind.
+    pub fn run<T, F>(&self, label: &str, task: F) -> Result<T, (FailureKind, anyhow::Error)>
+    where
+        T: Send + 'static,
+        F: Fn(&Cancellation) -> Result<T> + Send + Sync + Clone + 'static,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match run_with_timeout(self.timeout, task.clone()) {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            let kind = FailureKind::classify(&error);
+            if !kind.is_transient() || attempt >= self.max_attempts {
+                return Err((kind, error));
+            }
+            let backoff = self.backoff(attempt);
+            warn!(
+                "attempt {}/{} of {} failed ({}): {:#}, retrying in {} seconds",
+                attempt,
+                self.max_attempts,
+                label,
+                kind,
+                error,
+                backoff.as_secs()
+            );
+            thread::sleep(backoff);
+            attempt += 1;
+        }
+    }
+}
+
+/// Run a task on a separate thread and cancel it after the timeout.
+///
+/// A timed-out task is told to stop through its [Cancellation] and waited for, so that it never
+/// keeps running alongside the next attempt. Its result is discarded.
+pub fn run_with_timeout<T, F>(timeout: Option<Duration>, task: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Cancellation) -> Result<T> + Send + 'static,
+{
+    let cancellation = Cancellation::new();
+    let timeout = match timeout {
+        Some(t) => t,
+        None => return task(&cancellation),
+    };
+
+    let (sender, receiver) = channel();
+    let task_cancellation = cancellation.clone();
+    let handle = thread::spawn(move || {
+        let _ = sender.send(task(&task_cancellation));
+    });
+    let result = match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            cancellation.cancel();
+            Err(TimeoutError(timeout).into())
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow!("processing thread panicked")),
+    };
+    let _ = handle.join();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    #[test]
+    fn test_classify() {
+        let truncated: anyhow::Error =
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "eof").into();
+        assert_eq!(FailureKind::classify(&truncated), FailureKind::Truncated);
+
+        let network = anyhow!("error sending request for url").context("failed to open file");
+        assert_eq!(FailureKind::classify(&network), FailureKind::Network);
+
+        let timeout: anyhow::Error = TimeoutError(Duration::from_secs(1)).into();
+        assert_eq!(FailureKind::classify(&timeout), FailureKind::Timeout);
+
+        let parse = anyhow!("unsupported MRT type");
+        assert_eq!(FailureKind::classify(&parse), FailureKind::Parse);
+
+        // URLs of permanent failures don't make them network errors
+        let not_found = anyhow!(
+            "IO error for operation on https://remote.example/request/connection/rib.bz2: not found"
+        );
+        assert_eq!(FailureKind::classify(&not_found), FailureKind::Parse);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30),
+            timeout: None,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(10));
+        assert_eq!(policy.backoff(2), Duration::from_secs(20));
+        assert_eq!(policy.backoff(3), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_retry_transient_only() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            timeout: None,
+        };
+
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result: Result<(), _> = policy.run("test", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("connection reset by peer"))
+        });
+        assert_eq!(result.unwrap_err().0, FailureKind::Network);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let result: Result<(), _> = policy.run("test", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("invalid MRT header"))
+        });
+        assert_eq!(result.unwrap_err().0, FailureKind::Parse);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let running = Arc::new(AtomicBool::new(false));
+        let task_running = running.clone();
+        let result = run_with_timeout(Some(Duration::from_millis(10)), move |cancellation| {
+            task_running.store(true, Ordering::SeqCst);
+            while !cancellation.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            task_running.store(false, Ordering::SeqCst);
+            cancellation.check()
+        });
+        assert_eq!(
+            FailureKind::classify(&result.unwrap_err()),
+            FailureKind::Timeout
+        );
+        // the task has stopped by the time the timeout is reported
+        assert!(!running.load(Ordering::SeqCst));
+
+        assert_eq!(run_with_timeout(None, |_| Ok(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_retry_timeout_waits_for_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            timeout: Some(Duration::from_millis(10)),
+        };
+        // attempts running at the same time, and at most at once
+        let running = Arc::new(AtomicU32::new(0));
+        let peak = Arc::new(AtomicU32::new(0));
+        let (task_running, task_peak) = (running.clone(), peak.clone());
+        let result: Result<(), _> = policy.run("test", move |cancellation| {
+            let count = task_running.fetch_add(1, Ordering::SeqCst) + 1;
+            task_peak.fetch_max(count, Ordering::SeqCst);
+            while !cancellation.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            task_running.fetch_sub(1, Ordering::SeqCst);
+            cancellation.check()
+        });
+        assert_eq!(result.unwrap_err().0, FailureKind::Timeout);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+}
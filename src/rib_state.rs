@@ -0,0 +1,90 @@
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// A single route in a reconstructed RIB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RibEntry {
+    pub peer_asn: u32,
+    /// AS path from collector ([0]) to origin ([last]), `None` if it could not be parsed
+    pub as_path: Option<Vec<u32>>,
+}
+
+/// In-memory routing table state keyed by (peer IP, prefix).
+///
+/// Starts from a RIB dump and is brought forward in time by replaying UPDATES messages:
+/// announcements replace the route of the peer for the prefix, withdrawals remove it.
+#[derive(Debug, Clone, Default)]
+pub struct RibState {
+    entries: HashMap<(IpAddr, IpNet), RibEntry>,
+}
+
+impl RibState {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn apply_announcement(
+        &mut self,
+        peer_ip: IpAddr,
+        peer_asn: u32,
+        prefix: IpNet,
+        as_path: Option<Vec<u32>>,
+    ) {
+        self.entries
+            .insert((peer_ip, prefix), RibEntry { peer_asn, as_path });
+    }
+
+    pub fn apply_withdrawal(&mut self, peer_ip: IpAddr, prefix: IpNet) {
+        self.entries.remove(&(peer_ip, prefix));
+    }
+
+    pub fn get(&self, peer_ip: IpAddr, prefix: IpNet) -> Option<&RibEntry> {
+        self.entries.get(&(peer_ip, prefix))
+    }
+
+    /// Iterate over all routes as (peer IP, prefix, entry).
+    pub fn iter(&self) -> impl Iterator<Item = (IpAddr, IpNet, &RibEntry)> {
+        self.entries
+            .iter()
+            .map(|((peer_ip, prefix), entry)| (*peer_ip, *prefix, entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_updates() {
+        let peer: IpAddr = "192.0.2.1".parse().unwrap();
+        let prefix: IpNet = "1.1.1.0/24".parse().unwrap();
+        let other: IpNet = "1.0.0.0/24".parse().unwrap();
+
+        let mut state = RibState::new();
+        state.apply_announcement(peer, 64496, prefix, Some(vec![64496, 13335]));
+        state.apply_announcement(peer, 64496, other, Some(vec![64496, 13335]));
+        assert_eq!(state.len(), 2);
+
+        // implicit withdrawal: a new announcement replaces the previous route
+        state.apply_announcement(peer, 64496, prefix, Some(vec![64496, 174, 13335]));
+        assert_eq!(
+            state.get(peer, prefix).unwrap().as_path,
+            Some(vec![64496, 174, 13335])
+        );
+
+        state.apply_withdrawal(peer, other);
+        assert_eq!(state.len(), 1);
+        assert!(state.get(peer, other).is_none());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::DumpItem;
+use std::sync::{Condvar, Mutex};
+
+/// Estimated peak memory per byte of compressed RIB dump, dominated by the per-peer prefix sets
+/// of the peer-stats and pfx2as processors.
+pub const MEMORY_PER_DUMP_BYTE: u64 = 12;
+
+/// Memory assumed for dumps of unknown size.
+pub const DEFAULT_DUMP_MEMORY: u64 = 2 << 30;
+
+/// Estimate the peak memory of processing a dump file from its compressed size.
+pub fn estimate_memory(item: &DumpItem) -> u64 {
+    match item.rough_size {
+        size if size > 0 => size as u64 * MEMORY_PER_DUMP_BYTE,
+        _ => DEFAULT_DUMP_MEMORY,
+    }
+}
+
+/// Parse a memory size in bytes, with an optional binary `K`, `M`, `G` or `T` suffix, e.g. `48G`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => s.split_at(idx),
+        None => (s, ""),
+    };
+    let multiplier: u64 = match unit
+        .trim_end_matches(['B', 'b'])
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size {}, expected e.g. 512M or 48G", s)),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size {}, expected e.g. 512M or 48G", s))
+}
+
+/// Memory budget shared by parallel workers. Workers acquire the estimated memory of an item
+/// before processing it and block until enough of the budget is free.
+pub struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> Self {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Reserve memory until the returned permit is dropped. Items costing more than the whole
+    /// budget are capped to it, so that they run alone instead of never.
+    pub fn acquire(&self, cost: u64) -> MemoryPermit<'_> {
+        let cost = cost.min(self.limit);
+        let mut used = self.used.lock().unwrap();
+        while *used + cost > self.limit {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += cost;
+        MemoryPermit { budget: self, cost }
+    }
+
+    /// Currently reserved memory.
+    pub fn used(&self) -> u64 {
+        *self.used.lock().unwrap()
+    }
+}
+
+/// Memory reserved from a [MemoryBudget], released on drop.
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    cost: u64,
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        let mut used = self.budget.used.lock().unwrap();
+        *used -= self.cost;
+        self.budget.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_memory_budget() {
+        assert_eq!(parse_size("48G").unwrap(), 48 << 30);
+        assert_eq!(parse_size("512mb").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert!(parse_size("12X").is_err());
+
+        let budget = Arc::new(MemoryBudget::new(100));
+        let peak = Arc::new(AtomicU64::new(0));
+        let handles: Vec<_> = [60, 50, 40, 30, 200]
+            .into_iter()
+            .map(|cost| {
+                let (budget, peak) = (budget.clone(), peak.clone());
+                thread::spawn(move || {
+                    let _permit = budget.acquire(cost);
+                    peak.fetch_max(budget.used(), Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 100);
+        assert_eq!(budget.used(), 0);
+    }
+}
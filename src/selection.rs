@@ -0,0 +1,236 @@
+use crate::DumpItem;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Which RIB dumps of each collector and day to process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionPolicy {
+    /// every dump
+    #[default]
+    All,
+    /// the earliest dump of each day
+    FirstPerDay,
+    /// the dump closest to a time of day
+    ClosestTo(NaiveTime),
+    /// the dumps closest to every N hours from midnight
+    EveryNHours(u32),
+}
+
+/// A dump chosen by a [SelectionPolicy], with the time it was chosen for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// target time, `None` for the `all` and `first-per-day` policies
+    pub target: Option<NaiveDateTime>,
+    pub item: DumpItem,
+}
+
+/// Dump chosen for a day, as listed in a [SelectionReport].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SelectedDump {
+    /// target time of day as `HH:MM`
+    pub target: Option<String>,
+    /// unix timestamp of the dump
+    pub timestamp: i64,
+    pub url: String,
+}
+
+/// Dumps chosen for each collector and day (`YYYY-MM-DD`).
+pub type SelectionReport = BTreeMap<String, BTreeMap<String, Vec<SelectedDump>>>;
+
+impl SelectionPolicy {
+    /// Whether the dumps chosen for a day can change as later dumps of the day appear, i.e. for
+    /// policies targeting times of day.
+    pub fn needs_complete_days(&self) -> bool {
+        matches!(
+            self,
+            SelectionPolicy::ClosestTo(_) | SelectionPolicy::EveryNHours(_)
+        )
+    }
+
+    /// Select dumps per collector and day. If no dump exists at a target time, the nearest dump of
+    /// the same day is chosen instead; a dump chosen for several targets is only kept once.
+    pub fn select(&self, items: Vec<DumpItem>) -> Vec<Selection> {
+        if *self == SelectionPolicy::All {
+            return items
+                .into_iter()
+                .map(|item| Selection { target: None, item })
+                .collect();
+        }
+
+        let mut days: BTreeMap<(String, NaiveDate), Vec<DumpItem>> = BTreeMap::new();
+        for item in items {
+            days.entry((item.collector_id.clone(), item.ts_start.date()))
+                .or_default()
+                .push(item);
+        }
+
+        let mut selections = vec![];
+        for ((_, date), mut day_items) in days {
+            day_items.sort_by_key(|i| i.ts_start);
+            let targets: Vec<Option<NaiveDateTime>> = match self {
+                SelectionPolicy::All | SelectionPolicy::FirstPerDay => vec![None],
+                SelectionPolicy::ClosestTo(time) => vec![Some(date.and_time(*time))],
+                SelectionPolicy::EveryNHours(n) => (0..24)
+                    .step_by(*n as usize)
+                    .map(|hour| date.and_hms_opt(hour, 0, 0))
+                    .collect(),
+            };
+
+            let mut chosen = HashSet::new();
+            for target in targets {
+                let item = match target {
+                    None => day_items.first(),
+                    // the earliest of equally close dumps
+                    Some(t) => day_items
+                        .iter()
+                        .min_by_key(|i| (i.ts_start - t).num_seconds().abs()),
+                };
+                if let Some(item) = item {
+                    if chosen.insert(item.url.clone()) {
+                        selections.push(Selection {
+                            target,
+                            item: item.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        selections.sort_by(|a, b| {
+            (a.item.ts_start, &a.item.collector_id).cmp(&(b.item.ts_start, &b.item.collector_id))
+        });
+        selections
+    }
+}
+
+/// Group selected dumps by collector and day.
+pub fn selection_report(selections: &[Selection]) -> SelectionReport {
+    let mut report = SelectionReport::new();
+    for selection in selections {
+        let item = &selection.item;
+        report
+            .entry(item.collector_id.clone())
+            .or_default()
+            .entry(item.ts_start.format("%Y-%m-%d").to_string())
+            .or_default()
+            .push(SelectedDump {
+                target: selection.target.map(|t| t.format("%H:%M").to_string()),
+                timestamp: item.ts_start.and_utc().timestamp(),
+                url: item.url.clone(),
+            });
+    }
+    report
+}
+
+impl Display for SelectionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelectionPolicy::All => write!(f, "all"),
+            SelectionPolicy::FirstPerDay => write!(f, "first-per-day"),
+            SelectionPolicy::ClosestTo(time) => write!(f, "closest-to-{}", time.format("%H:%M")),
+            SelectionPolicy::EveryNHours(n) => write!(f, "every-{}-hours", n),
+        }
+    }
+}
+
+impl FromStr for SelectionPolicy {
+    type Err = String;
+
+    /// Parse `all`, `first-per-day`, `closest-to-HH:MM` or `every-N-hours`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "unknown selection policy {}, expected all, first-per-day, closest-to-HH:MM or every-N-hours",
+                s
+            )
+        };
+        if let Some(time) = s.strip_prefix("closest-to-") {
+            return NaiveTime::parse_from_str(time, "%H:%M")
+                .map(SelectionPolicy::ClosestTo)
+                .map_err(|_| err());
+        }
+        if let Some(hours) = s
+            .strip_prefix("every-")
+            .and_then(|h| h.strip_suffix("-hours"))
+        {
+            return match hours.parse::<u32>() {
+                Ok(n) if (1..=24).contains(&n) => Ok(SelectionPolicy::EveryNHours(n)),
+                _ => Err(format!("invalid interval in {}, expected 1 to 24 hours", s)),
+            };
+        }
+        match s {
+            "all" => Ok(SelectionPolicy::All),
+            "first-per-day" => Ok(SelectionPolicy::FirstPerDay),
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(collector_id: &str, ts: &str) -> DumpItem {
+        DumpItem::test_rib(
+            collector_id,
+            NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M").unwrap(),
+            &format!("{}-{}", collector_id, ts),
+        )
+    }
+
+    #[test]
+    fn test_select() {
+        let items = vec![
+            item("rrc00", "2022-02-01 00:00"),
+            item("rrc00", "2022-02-01 08:00"),
+            item("rrc00", "2022-02-01 16:00"),
+            item("rrc00", "2022-02-02 08:00"),
+            // first dump of the day at 02:00
+            item("route-views2", "2022-02-01 02:00"),
+            item("route-views2", "2022-02-01 14:00"),
+        ];
+        let urls = |policy: &str| -> Vec<String> {
+            policy
+                .parse::<SelectionPolicy>()
+                .unwrap()
+                .select(items.clone())
+                .into_iter()
+                .map(|s| s.item.url)
+                .collect()
+        };
+
+        assert_eq!(urls("all").len(), 6);
+        assert_eq!(
+            urls("first-per-day"),
+            vec![
+                "rrc00-2022-02-01 00:00",
+                "route-views2-2022-02-01 02:00",
+                "rrc00-2022-02-02 08:00"
+            ]
+        );
+        assert_eq!(
+            urls("closest-to-12:00"),
+            vec![
+                "rrc00-2022-02-01 08:00",
+                "route-views2-2022-02-01 14:00",
+                "rrc00-2022-02-02 08:00"
+            ]
+        );
+        // targets 00:00 and 12:00, falling back to the nearest dumps
+        assert_eq!(urls("every-12-hours").len(), 5);
+
+        let selections = SelectionPolicy::EveryNHours(12).select(items.clone());
+        let report = selection_report(&selections);
+        let day = &report["route-views2"]["2022-02-01"];
+        assert_eq!(day.len(), 2);
+        assert_eq!(day[0].target.as_deref(), Some("00:00"));
+        assert_eq!(day[0].url, "route-views2-2022-02-01 02:00");
+
+        assert!(!SelectionPolicy::FirstPerDay.needs_complete_days());
+        assert!(SelectionPolicy::EveryNHours(12).needs_complete_days());
+        assert!("every-0-hours".parse::<SelectionPolicy>().is_err());
+        assert!("closest-to-25:00".parse::<SelectionPolicy>().is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::atomic::write_atomic;
+use crate::manifest::ItemStatus;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Item counts and processing time of a collector in a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CollectorSummary {
+    pub skipped: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// total processing time of the collector's items in seconds
+    pub duration_secs: f64,
+}
+
+/// Machine-readable summary of a bootstrap run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub planned: usize,
+    pub skipped: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// compressed size of the processed dump files, as reported by the broker or file system
+    pub bytes_read: u64,
+    /// number of routes processed
+    pub elems_processed: u64,
+    /// wall-clock duration of the run in seconds
+    pub duration_secs: f64,
+    /// time ranges whose dump files could not be queried
+    pub failed_queries: usize,
+    pub collectors: BTreeMap<String, CollectorSummary>,
+}
+
+impl RunSummary {
+    pub fn new(planned: usize) -> Self {
+        RunSummary {
+            planned,
+            ..Default::default()
+        }
+    }
+
+    /// Record the outcome of a single item.
+    pub fn record(
+        &mut self,
+        collector: &str,
+        status: ItemStatus,
+        duration: Duration,
+        bytes_read: u64,
+        elems_processed: u64,
+    ) {
+        let collector = self.collectors.entry(collector.to_string()).or_default();
+        collector.duration_secs += duration.as_secs_f64();
+        match status {
+            ItemStatus::Skipped => {
+                self.skipped += 1;
+                collector.skipped += 1;
+            }
+            ItemStatus::Succeeded => {
+                self.succeeded += 1;
+                collector.succeeded += 1;
+            }
+            ItemStatus::Failed | ItemStatus::Started => {
+                self.failed += 1;
+                collector.failed += 1;
+            }
+        }
+        self.bytes_read += bytes_read;
+        self.elems_processed += elems_processed;
+    }
+
+    /// Summary of a time range whose dump files could not be queried.
+    pub fn failed_query() -> Self {
+        RunSummary {
+            failed_queries: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Add the counts and durations of another run, e.g. of the next chunk of a backfill.
+    pub fn merge(&mut self, other: RunSummary) {
+        self.planned += other.planned;
+        self.skipped += other.skipped;
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.bytes_read += other.bytes_read;
+        self.elems_processed += other.elems_processed;
+        self.duration_secs += other.duration_secs;
+        self.failed_queries += other.failed_queries;
+        for (name, other) in other.collectors {
+            let collector = self.collectors.entry(name).or_default();
+            collector.skipped += other.skipped;
+            collector.succeeded += other.succeeded;
+            collector.failed += other.failed;
+            collector.duration_secs += other.duration_secs;
+        }
+    }
+
+    /// Share of failed items among the processed (not skipped) ones, 0 if none were processed.
+    pub fn failure_ratio(&self) -> f64 {
+        match self.succeeded + self.failed {
+            0 => 0.0,
+            processed => self.failed as f64 / processed as f64,
+        }
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self, timestamp: i64) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP peer_stats_bootstrap_items Dump files of the last bootstrap run by status.\n\
+             # TYPE peer_stats_bootstrap_items gauge"
+        );
+        for (status, count) in [
+            ("planned", self.planned),
+            ("skipped", self.skipped),
+            ("succeeded", self.succeeded),
+            ("failed", self.failed),
+        ] {
+            let _ = writeln!(
+                out,
+                "peer_stats_bootstrap_items{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+        for (name, help, value) in [
+            (
+                "bytes_read",
+                "Compressed size of the processed dump files.",
+                self.bytes_read as f64,
+            ),
+            (
+                "elems_processed",
+                "Routes processed.",
+                self.elems_processed as f64,
+            ),
+            (
+                "duration_seconds",
+                "Wall-clock duration of the run.",
+                self.duration_secs,
+            ),
+            (
+                "failed_queries",
+                "Time ranges whose dump files could not be queried.",
+                self.failed_queries as f64,
+            ),
+            (
+                "failure_ratio",
+                "Share of failed among processed dump files.",
+                self.failure_ratio(),
+            ),
+            (
+                "last_run_timestamp_seconds",
+                "End time of the last run.",
+                timestamp as f64,
+            ),
+        ] {
+            let _ = writeln!(
+                out,
+                "# HELP peer_stats_bootstrap_{name} {help}\n\
+                 # TYPE peer_stats_bootstrap_{name} gauge\n\
+                 peer_stats_bootstrap_{name} {value}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP peer_stats_bootstrap_collector_duration_seconds Processing time per collector.\n\
+             # TYPE peer_stats_bootstrap_collector_duration_seconds gauge"
+        );
+        for (collector, summary) in &self.collectors {
+            let _ = writeln!(
+                out,
+                "peer_stats_bootstrap_collector_duration_seconds{{collector=\"{}\"}} {}",
+                collector, summary.duration_secs
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP peer_stats_bootstrap_collector_failed Failed dump files per collector.\n\
+             # TYPE peer_stats_bootstrap_collector_failed gauge"
+        );
+        for (collector, summary) in &self.collectors {
+            let _ = writeln!(
+                out,
+                "peer_stats_bootstrap_collector_failed{{collector=\"{}\"}} {}",
+                collector, summary.failed
+            );
+        }
+        out
+    }
+
+    /// Write metrics for the node_exporter textfile collector, replacing the file atomically so
+    /// that it is never scraped half-written.
+    pub fn write_prometheus(&self, path: &Path, timestamp: i64) -> Result<()> {
+        write_atomic(path, |mut file| {
+            file.write_all(self.to_prometheus(timestamp).as_bytes())?;
+            Ok(file)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::new(4);
+        let secs = Duration::from_secs;
+        summary.record("rrc00", ItemStatus::Succeeded, secs(60), 1000, 50);
+        summary.record("rrc00", ItemStatus::Failed, secs(30), 500, 0);
+        summary.record("route-views2", ItemStatus::Skipped, secs(0), 0, 0);
+        summary.record("route-views2", ItemStatus::Succeeded, secs(10), 200, 20);
+
+        assert_eq!(
+            (summary.succeeded, summary.failed, summary.skipped),
+            (2, 1, 1)
+        );
+        assert_eq!(summary.bytes_read, 1700);
+        assert_eq!(summary.elems_processed, 70);
+        assert_eq!(summary.collectors["rrc00"].duration_secs, 90.0);
+        assert!((summary.failure_ratio() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(RunSummary::new(0).failure_ratio(), 0.0);
+
+        let metrics = summary.to_prometheus(1700000000);
+        assert!(metrics.contains("peer_stats_bootstrap_items{status=\"failed\"} 1\n"));
+        assert!(metrics
+            .contains("peer_stats_bootstrap_collector_duration_seconds{collector=\"rrc00\"} 90\n"));
+        assert!(metrics.contains("peer_stats_bootstrap_last_run_timestamp_seconds 1700000000\n"));
+
+        let mut next = RunSummary::new(2);
+        next.record("rrc00", ItemStatus::Succeeded, secs(30), 100, 10);
+        summary.merge(next);
+        summary.merge(RunSummary::failed_query());
+        assert_eq!((summary.planned, summary.succeeded), (6, 3));
+        assert_eq!(summary.failed_queries, 1);
+        assert_eq!(summary.collectors["rrc00"].succeeded, 2);
+        assert_eq!(summary.collectors["rrc00"].duration_secs, 120.0);
+    }
+}
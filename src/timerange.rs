@@ -0,0 +1,174 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime};
+
+/// Parse a time expression, relative to `now` where applicable:
+///
+/// - unix timestamps, e.g. `1643673600`
+/// - RFC 3339 times, e.g. `2022-02-01T00:00:00Z`, or `YYYY-MM-DDTHH:MM:SS` in UTC
+/// - `YYYY-MM-DD` dates, at midnight UTC
+/// - `now`, `today`, `yesterday` or `tomorrow`, the latter three at midnight UTC
+/// - any of these keywords followed by an offset such as `-3d` or `+12h`, or the offset alone
+///   relative to now; offsets are given in `s`, `m`, `h`, `d` or `w`
+pub fn parse_time(expr: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let expr = expr.trim();
+    let err = || {
+        format!(
+            "invalid time {}, expected a unix timestamp, RFC 3339 time, YYYY-MM-DD date, \
+             now, today, yesterday or relative time such as -3d",
+            expr
+        )
+    };
+
+    if !expr.is_empty() && expr.bytes().all(|b| b.is_ascii_digit()) {
+        return expr
+            .parse::<i64>()
+            .ok()
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .map(|t| t.naive_utc())
+            .ok_or_else(err);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(expr) {
+        return Ok(t.naive_utc());
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(expr, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(t);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(expr, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+
+    let (anchor, offset) = match expr.find(['+', '-']) {
+        Some(idx) => expr.split_at(idx),
+        None => (expr, ""),
+    };
+    let today = now.date().and_hms_opt(0, 0, 0).unwrap();
+    let anchor = match anchor {
+        "" if !offset.is_empty() => now,
+        "now" => now,
+        "today" => today,
+        "yesterday" => today - Duration::days(1),
+        "tomorrow" => today + Duration::days(1),
+        _ => return Err(err()),
+    };
+    match offset {
+        "" => Ok(anchor),
+        _ => parse_offset(offset)
+            .and_then(|offset| anchor.checked_add_signed(offset))
+            .ok_or_else(err),
+    }
+}
+
+/// Parse a signed offset such as `-3d` or `+12h`.
+fn parse_offset(offset: &str) -> Option<Duration> {
+    let (sign, rest) = match offset.split_at(1) {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (number, unit) = rest.split_at(rest.find(|c: char| !c.is_ascii_digit())?);
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+    let secs = number.parse::<i64>().ok()?.checked_mul(unit_secs)?;
+    Duration::try_seconds(sign * secs)
+}
+
+/// Parse the start and end of a time range, failing if the end is before the start.
+pub fn parse_time_range(
+    start: &str,
+    end: &str,
+    now: NaiveDateTime,
+) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let (ts_start, ts_end) = (parse_time(start, now)?, parse_time(end, now)?);
+    if ts_end < ts_start {
+        return Err(format!(
+            "end time {} ({}) is before start time {} ({})",
+            end, ts_end, start, ts_start
+        ));
+    }
+    Ok((ts_start, ts_end))
+}
+
+/// Time range from midnight `days` days ago until now.
+pub fn last_days(days: u32, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+    let today = now.date().and_hms_opt(0, 0, 0).unwrap();
+    (today - Duration::days(days as i64), now)
+}
+
+/// Monthly time ranges of the given number of months before the current one, most recent first,
+/// each ending at the first day of the following month.
+pub fn backfill_ranges(today: NaiveDate, months: u32) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let month_start = today.with_day(1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    (1..=months)
+        .map(|m| {
+            (
+                month_start.checked_sub_months(Months::new(m)).unwrap(),
+                month_start.checked_sub_months(Months::new(m - 1)).unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = time("2023-02-15 13:30");
+        let parse = |expr: &str| parse_time(expr, now).unwrap();
+
+        assert_eq!(parse("1643673600"), time("2022-02-01 00:00"));
+        assert_eq!(parse("2022-02-01T08:00:00+02:00"), time("2022-02-01 06:00"));
+        assert_eq!(parse("2022-02-01T08:00:00"), time("2022-02-01 08:00"));
+        assert_eq!(parse("2022-02-01"), time("2022-02-01 00:00"));
+        assert_eq!(parse("now"), now);
+        assert_eq!(parse("today"), time("2023-02-15 00:00"));
+        assert_eq!(parse("yesterday"), time("2023-02-14 00:00"));
+        assert_eq!(parse("-3d"), time("2023-02-12 13:30"));
+        assert_eq!(parse("today-3d"), time("2023-02-12 00:00"));
+        assert_eq!(parse("tomorrow+12h"), time("2023-02-16 12:00"));
+        assert_eq!(parse("-1w"), time("2023-02-08 13:30"));
+
+        for expr in [
+            "",
+            "-3",
+            "3d",
+            "-3y",
+            "today-",
+            "last week",
+            "2022-13-01",
+            "-d",
+        ] {
+            assert!(parse_time(expr, now).is_err(), "{}", expr);
+        }
+
+        assert!(parse_time_range("today", "-1d", now).is_err());
+        assert_eq!(
+            parse_time_range("-1d", "now", now).unwrap(),
+            (time("2023-02-14 13:30"), now)
+        );
+        assert_eq!(last_days(3, now), (time("2023-02-12 00:00"), now));
+    }
+
+    #[test]
+    fn test_backfill_ranges() {
+        let today = NaiveDate::from_ymd_opt(2023, 2, 15).unwrap();
+        assert_eq!(
+            backfill_ranges(today, 3),
+            vec![
+                (time("2023-01-01 00:00"), time("2023-02-01 00:00")),
+                (time("2022-12-01 00:00"), time("2023-01-01 00:00")),
+                (time("2022-11-01 00:00"), time("2022-12-01 00:00")),
+            ]
+        );
+    }
+}
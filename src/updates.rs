@@ -0,0 +1,300 @@
+use crate::as2rel::As2Rel;
+use crate::pfx2as::{Prefix2As, Prefix2AsCount};
+use ipnet::IpNet;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatesStats {
+    pub project: String,
+    pub collector: String,
+    /// start of the processed time window (unix timestamp)
+    pub ts_start: i64,
+    /// end of the processed time window (unix timestamp)
+    pub ts_end: i64,
+    pub updates_dump_urls: Vec<String>,
+    /// announcement and withdrawal counts per peer
+    pub peers: HashMap<IpAddr, UpdatesPeerCount>,
+    /// announcement and withdrawal counts per prefix
+    pub prefixes: Vec<PrefixChurnCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatesPeerCount {
+    pub ip: IpAddr,
+    pub asn: u32,
+    pub num_announcements: usize,
+    pub num_withdrawals: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixChurnCount {
+    pub prefix: String,
+    pub num_announcements: usize,
+    pub num_withdrawals: usize,
+    /// number of peers sending updates for this prefix
+    pub num_peers: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatesNew {
+    pub project: String,
+    pub collector: String,
+    /// start of the processed time window (unix timestamp)
+    pub ts_start: i64,
+    /// end of the processed time window (unix timestamp)
+    pub ts_end: i64,
+    pub updates_dump_urls: Vec<String>,
+    /// (prefix, origin) pairs announced in the window and not present in the baseline,
+    /// `count` being the number of announcements
+    pub pfx2as: Vec<Prefix2AsCount>,
+    /// AS links announced in the window and not present in the baseline
+    pub as_links: Vec<AsLinkCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AsLinkCount {
+    /// the smaller ASN of the link
+    pub asn1: u32,
+    /// the larger ASN of the link
+    pub asn2: u32,
+    /// number of announcements with paths containing this link
+    pub paths_count: usize,
+}
+
+/// Known (prefix, origin) pairs and AS links, usually taken from the RIB at the window start.
+///
+/// Only pairs and links absent from the baseline are reported as newly observed.
+#[derive(Debug, Clone, Default)]
+pub struct UpdatesBaseline {
+    pfx2as: HashSet<(String, u32)>,
+    as_links: HashSet<(u32, u32)>,
+}
+
+impl UpdatesBaseline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_pfx2as_as2rel(pfx2as: &Prefix2As, as2rel: &As2Rel) -> Self {
+        let mut baseline = Self::new();
+        for entry in &pfx2as.pfx2as {
+            baseline.pfx2as.insert((entry.prefix.clone(), entry.asn));
+        }
+        for entry in &as2rel.as2rel {
+            baseline.as_links.insert(link_key(entry.asn1, entry.asn2));
+        }
+        baseline
+    }
+}
+
+fn link_key(asn1: u32, asn2: u32) -> (u32, u32) {
+    match asn1 <= asn2 {
+        true => (asn1, asn2),
+        false => (asn2, asn1),
+    }
+}
+
+/// Processor for collecting announcement and withdrawal statistics from BGP UPDATES dumps.
+pub struct UpdatesProcessor {
+    baseline: UpdatesBaseline,
+    peer_asn_map: HashMap<IpAddr, u32>,
+    peer_counts: HashMap<IpAddr, (usize, usize)>,
+    prefix_counts: HashMap<IpNet, (usize, usize, HashSet<IpAddr>)>,
+    new_pfx2as: HashMap<(String, u32), usize>,
+    new_as_links: HashMap<(u32, u32), usize>,
+}
+
+impl UpdatesProcessor {
+    pub fn new() -> Self {
+        Self::with_baseline(UpdatesBaseline::new())
+    }
+
+    pub fn with_baseline(baseline: UpdatesBaseline) -> Self {
+        Self {
+            baseline,
+            peer_asn_map: HashMap::new(),
+            peer_counts: HashMap::new(),
+            prefix_counts: HashMap::new(),
+            new_pfx2as: HashMap::new(),
+            new_as_links: HashMap::new(),
+        }
+    }
+
+    /// Process a single announcement or withdrawal.
+    ///
+    /// Withdrawals carry no AS path. AS paths must be from collector (`[0]`) to origin (`[last]`).
+    pub fn process_element(
+        &mut self,
+        peer_ip: IpAddr,
+        peer_asn: u32,
+        prefix: IpNet,
+        is_announcement: bool,
+        as_path: Option<&[u32]>,
+    ) {
+        self.peer_asn_map.entry(peer_ip).or_insert(peer_asn);
+
+        let (peer_announcements, peer_withdrawals) =
+            self.peer_counts.entry(peer_ip).or_insert((0, 0));
+        let (pfx_announcements, pfx_withdrawals, peers) = self
+            .prefix_counts
+            .entry(prefix)
+            .or_insert((0, 0, HashSet::new()));
+        peers.insert(peer_ip);
+
+        if !is_announcement {
+            *peer_withdrawals += 1;
+            *pfx_withdrawals += 1;
+            return;
+        }
+        *peer_announcements += 1;
+        *pfx_announcements += 1;
+
+        let as_path = match as_path {
+            Some(p) => p,
+            None => return,
+        };
+
+        if let Some(origin) = as_path.last().copied() {
+            let key = (prefix.to_string(), origin);
+            if !self.baseline.pfx2as.contains(&key) {
+                *self.new_pfx2as.entry(key).or_insert(0) += 1;
+            }
+        }
+
+        for (asn1, asn2) in as_path.iter().tuple_windows::<(&u32, &u32)>() {
+            if asn1 == asn2 {
+                continue;
+            }
+            let key = link_key(*asn1, *asn2);
+            if !self.baseline.as_links.contains(&key) {
+                *self.new_as_links.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn into_updates(
+        self,
+        project: &str,
+        collector: &str,
+        ts_start: i64,
+        ts_end: i64,
+        updates_dump_urls: &[String],
+    ) -> (UpdatesStats, UpdatesNew) {
+        let peers = self
+            .peer_counts
+            .into_iter()
+            .map(|(ip, (num_announcements, num_withdrawals))| {
+                (
+                    ip,
+                    UpdatesPeerCount {
+                        ip,
+                        asn: self.peer_asn_map.get(&ip).copied().unwrap_or_default(),
+                        num_announcements,
+                        num_withdrawals,
+                    },
+                )
+            })
+            .collect();
+
+        let prefixes = self
+            .prefix_counts
+            .into_iter()
+            .map(
+                |(prefix, (num_announcements, num_withdrawals, peers))| PrefixChurnCount {
+                    prefix: prefix.to_string(),
+                    num_announcements,
+                    num_withdrawals,
+                    num_peers: peers.len(),
+                },
+            )
+            .collect();
+
+        let pfx2as = self
+            .new_pfx2as
+            .into_iter()
+            .map(|((prefix, asn), count)| Prefix2AsCount { prefix, asn, count })
+            .collect();
+
+        let as_links = self
+            .new_as_links
+            .into_iter()
+            .map(|((asn1, asn2), paths_count)| AsLinkCount {
+                asn1,
+                asn2,
+                paths_count,
+            })
+            .collect();
+
+        (
+            UpdatesStats {
+                project: project.to_string(),
+                collector: collector.to_string(),
+                ts_start,
+                ts_end,
+                updates_dump_urls: updates_dump_urls.to_vec(),
+                peers,
+                prefixes,
+            },
+            UpdatesNew {
+                project: project.to_string(),
+                collector: collector.to_string(),
+                ts_start,
+                ts_end,
+                updates_dump_urls: updates_dump_urls.to_vec(),
+                pfx2as,
+                as_links,
+            },
+        )
+    }
+}
+
+impl Default for UpdatesProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_processor() {
+        let peer: IpAddr = "192.0.2.1".parse().unwrap();
+        let known: IpNet = "1.1.1.0/24".parse().unwrap();
+        let moved: IpNet = "1.0.0.0/24".parse().unwrap();
+
+        let mut baseline = UpdatesBaseline::new();
+        baseline.pfx2as.insert((known.to_string(), 13335));
+        baseline.pfx2as.insert((moved.to_string(), 13335));
+        baseline.as_links.insert(link_key(64496, 13335));
+
+        let mut processor = UpdatesProcessor::with_baseline(baseline);
+        processor.process_element(peer, 64496, known, true, Some(&[64496, 13335]));
+        processor.process_element(peer, 64496, known, false, None);
+        processor.process_element(peer, 64496, moved, true, Some(&[64496, 174, 64511]));
+
+        let (stats, new) = processor.into_updates("route-views", "route-views2", 0, 3600, &[]);
+
+        let peer_count = stats.peers.get(&peer).unwrap();
+        assert_eq!(
+            (peer_count.num_announcements, peer_count.num_withdrawals),
+            (2, 1)
+        );
+        let churn = stats
+            .prefixes
+            .iter()
+            .find(|p| p.prefix == known.to_string())
+            .unwrap();
+        assert_eq!((churn.num_announcements, churn.num_withdrawals), (1, 1));
+
+        assert_eq!(new.pfx2as.len(), 1);
+        assert_eq!(new.pfx2as[0].asn, 64511);
+        let mut links: Vec<(u32, u32)> = new.as_links.iter().map(|l| (l.asn1, l.asn2)).collect();
+        links.sort();
+        assert_eq!(links, vec![(174, 64496), (174, 64511)]);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::atomic::write_atomic;
+use crate::DumpItem;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Position of the bootstrap watch mode, persisted between polls and restarts.
+///
+/// Each poll queries the dump files from `since` until now and skips the ones already processed,
+/// so that dumps indexed late by the broker are still picked up as long as they are within the
+/// window, while no dump is processed twice. Failed dumps are tried again in the next polls, up to
+/// a maximum number of attempts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchCursor {
+    /// start of the window of the next poll, unix timestamp
+    pub since: i64,
+    /// URLs and timestamps of the processed dumps at or after `since`
+    pub processed: BTreeMap<String, i64>,
+    /// dumps at or after `since` that failed so far, by URL
+    #[serde(default)]
+    pub failed: BTreeMap<String, FailedDump>,
+}
+
+/// A dump that failed in earlier polls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedDump {
+    /// unix timestamp of the dump
+    pub timestamp: i64,
+    pub attempts: u32,
+}
+
+impl WatchCursor {
+    pub fn new(since: i64) -> Self {
+        WatchCursor {
+            since,
+            ..Default::default()
+        }
+    }
+
+    /// Load the cursor from a file, `None` if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read watch cursor {}", path.display()))?;
+        let cursor = serde_json::from_str(content.as_str())
+            .with_context(|| format!("invalid watch cursor {}", path.display()))?;
+        Ok(Some(cursor))
+    }
+
+    /// Save the cursor, replacing the file atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |mut file| {
+            serde_json::to_writer_pretty(&mut file, self)?;
+            Ok(file)
+        })
+        .with_context(|| format!("couldn't save watch cursor {}", path.display()))
+    }
+
+    pub fn is_processed(&self, item: &DumpItem) -> bool {
+        self.processed.contains_key(&item.url)
+    }
+
+    pub fn mark_processed(&mut self, item: &DumpItem) {
+        self.failed.remove(&item.url);
+        self.processed
+            .insert(item.url.clone(), item.ts_start.and_utc().timestamp());
+    }
+
+    /// Record a failed attempt of a dump, marking it as processed once it failed `max_attempts`
+    /// times. Returns whether the dump was given up.
+    pub fn mark_failed(&mut self, item: &DumpItem, max_attempts: u32) -> bool {
+        let failed = self.failed.entry(item.url.clone()).or_insert(FailedDump {
+            timestamp: item.ts_start.and_utc().timestamp(),
+            attempts: 0,
+        });
+        failed.attempts += 1;
+        if failed.attempts < max_attempts {
+            return false;
+        }
+        self.mark_processed(item);
+        true
+    }
+
+    /// Move the start of the window forward to `since`, forgetting the dumps before it.
+    pub fn advance(&mut self, since: i64) {
+        if since > self.since {
+            self.since = since;
+            self.processed.retain(|_, ts| *ts >= since);
+            self.failed.retain(|_, failed| failed.timestamp >= since);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn item(url: &str, ts: i64) -> DumpItem {
+        DumpItem::test_rib(
+            "rrc00",
+            DateTime::from_timestamp(ts, 0).unwrap().naive_utc(),
+            url,
+        )
+    }
+
+    #[test]
+    fn test_watch_cursor() {
+        let path =
+            std::env::temp_dir().join(format!("watch-cursor-test-{}.json", std::process::id()));
+        assert!(WatchCursor::load(&path).unwrap().is_none());
+
+        let mut cursor = WatchCursor::new(1000);
+        cursor.mark_processed(&item("a", 1000));
+        cursor.mark_processed(&item("b", 2000));
+        assert!(!cursor.mark_failed(&item("d", 1800), 2));
+        cursor.save(&path).unwrap();
+
+        let mut cursor = WatchCursor::load(&path).unwrap().unwrap();
+        assert!(cursor.is_processed(&item("a", 1000)));
+        assert!(!cursor.is_processed(&item("c", 1500)));
+        // failed dumps are retried until they reach the maximum attempts
+        assert!(!cursor.is_processed(&item("d", 1800)));
+        assert!(cursor.mark_failed(&item("d", 1800), 2));
+        assert!(cursor.is_processed(&item("d", 1800)));
+        assert!(cursor.failed.is_empty());
+        assert!(!cursor.mark_failed(&item("e", 1200), 2));
+
+        cursor.advance(1500);
+        assert_eq!(cursor.since, 1500);
+        assert!(!cursor.is_processed(&item("a", 1000)));
+        assert!(cursor.is_processed(&item("b", 2000)));
+        assert!(cursor.failed.is_empty());
+        // never moves backwards
+        cursor.advance(500);
+        assert_eq!(cursor.since, 1500);
+
+        fs::remove_file(path).unwrap();
+    }
+}